@@ -15,7 +15,6 @@ enum HelloCounterMsg {
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum HelloCounterCalls {
     QueryCount,
-    CountValue(u64),
 }
 
 /// The actor that counts the messages.
@@ -37,7 +36,9 @@ impl HelloCounterActor {
 impl Actor for HelloCounterActor {
     type SendMessage = HelloCounterMsg;
     type CallMessage = HelloCounterCalls;
+    type CallReply = u64;
     type ErrorType = ();
+    type FutureResult = ();
 
     async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
         match msg {
@@ -48,15 +49,12 @@ impl Actor for HelloCounterActor {
         }
     }
 
-    async fn handle_calls(&mut self, msg: Self::CallMessage) -> (Control, Result<Self::CallMessage, Self::ErrorType>) {
+    async fn handle_calls(&mut self, msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
         match msg {
             HelloCounterCalls::QueryCount => {
                 // return the count
                 // note that since the actor is single-threaded we dont need to bother with complex synchronization logic
-                (Control::Ok, Ok(HelloCounterCalls::CountValue(self.count)))
-            }
-            _ => {
-                panic!("impossible call message received");
+                (Control::Ok, Ok(self.count))
             }
         }
     }
@@ -71,10 +69,8 @@ async fn main() {
     for _i in 0..5082 {
         actor_ref.send(HelloCounterMsg::Hello).await.expect("unable to send message");
     }
-    let reply = actor_ref.call(HelloCounterCalls::QueryCount).await.unwrap().unwrap();
-    if let HelloCounterCalls::CountValue(count) = reply {
-        println!("count is {}", count);
-    }
+    let count = actor_ref.call(HelloCounterCalls::QueryCount).await.unwrap().unwrap();
+    println!("count is {}", count);
     actor_ref.shutdown().await.unwrap();
-    handle.await.expect("error waiting for handle");
+    handle.await;
 }
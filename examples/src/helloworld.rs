@@ -25,7 +25,9 @@ impl Actor for HelloWorldActor {
     type SendMessage = HelloMsg;
     /// We're not using these types.
     type CallMessage = ();
+    type CallReply = ();
     type ErrorType = ();
+    type FutureResult = ();
 
     async fn handle_sends(&mut self, msg: HelloMsg) -> Control {
         match msg {
@@ -49,5 +51,5 @@ async fn main() {
     // shutdown the actor straight away, it will finish processing messages before it shuts down
     actor_ref.shutdown().await.unwrap();
     // wait for the actor task to finish
-    handle.await.expect("error waiting for handle");
+    handle.await;
 }
@@ -1,16 +1,17 @@
 use core::future::Future;
 use std::marker::{Send, Sync};
 use log::warn;
-use tokio::task::JoinHandle;
-use crate::result::Result;
+use tokio_util::sync::CancellationToken;
+use crate::result::{Error, Result};
+use crate::actor_handle::{ActorHandle, ExitGuard};
 use crate::actor_ref::ActorRef;
+use crate::config::{ActorConfig, MailboxCapacity};
 use crate::control::Control;
 use crate::executor::ActorExecutor;
-
-
-/// The default size of the actor channel buffer. The channel buffers incoming messages, once it is
-/// full then sending threads will wait for space in the buffer.
-const DEFAULT_ACTOR_BUFFER_SIZE: usize = 10;
+use crate::mailbox::{mailbox, Priority};
+use crate::reply_token::ReplyToken;
+use crate::monitor::Down;
+use crate::stream::StreamId;
 
 /// The Actor trait. This is the trait that structs will need to implement to function as an actor.
 ///
@@ -20,7 +21,7 @@ const DEFAULT_ACTOR_BUFFER_SIZE: usize = 10;
 /// threaded fashion meaning that there are no concurrency issues within the actor itself.
 ///
 /// A struct can become an actor by implementing this trait. Instances of the actor are created
-/// using the [create_actor()] function. This function returns an [ActorRef] which is used to
+/// using the [create_actor()] or [create_actor_with_config()] functions. This function returns an [ActorRef] which is used to
 /// control the instance and to send it messages. [ActorRef]s can be freely cloned and sent, there
 /// can be as many references to an actor instance as required.
 ///
@@ -32,7 +33,11 @@ const DEFAULT_ACTOR_BUFFER_SIZE: usize = 10;
 /// * on_initialization() - this will be called immediately after the actor is created
 /// * handle_sends() - this is called whenever the actor receives a send message
 /// * handle_calls() - this is called whenever the actor receives a call message
+/// * handle_future() - this is called when a future spawned with [Control::SpawnFutureWithResult] completes
+/// * handle_down() - this is called when an actor that is monitored by this actor stops
+/// * on_stream_finished() - this is called when a stream attached with [ActorRef::attach_stream()] finishes
 /// * on_shutdown() - this is called when the actor is being shut down
+/// * on_panic() - this is called if one of the other functions panics
 ///
 /// These functions return a [Control] enum, which enables the actor to control its execution,
 /// including shutdown and termination. [Control] also enables the integration of tasks
@@ -46,22 +51,38 @@ const DEFAULT_ACTOR_BUFFER_SIZE: usize = 10;
 /// This framework provides the capability to integrate with futures created outside of the
 /// framework.
 ///
+/// A future returned in [Control::SpawnFuture] is spawned as a separate task, its output is
+/// ignored. A future returned in [Control::SpawnFutureWithResult] is also spawned as a separate
+/// task, but when it completes its output is sent back to the actor and handled by handle_future().
+/// The output is delivered like a system message, so it is handled before any send and call
+/// messages that are waiting in the mailbox and it never waits for space in a full mailbox. This
+/// enables an actor to start asynchronous I/O without blocking its message processing and to
+/// update its state with the result.
+///
 /// ## Shutdown, and Termination
 ///
-/// todo: implement
 /// A shutdown is a controlled shutdown of the actor. It completes execution of all messages
 /// that were received prior to the shutdown instruction, awaits any registered futures, and then
 /// shuts down. Messages that are received after the shutdown instruction
 /// are discarded. In the case of send messages this has no direct effect and in the case of call messages
 /// this will result in an error for the calling task. The on_shutdown() function is called.
 ///
-/// todo: implement
-/// A termination is an quicker shutdown of the actor. Messages that were sent
+/// An actor is also shut down when all [ActorRef]s to it have been dropped, after it has handled
+/// the messages in its mailbox.
+///
+/// A termination is a quicker shutdown of the actor. Messages that were sent
 /// prior to the termination are discarded. Any futures that were registered and that are still active
 /// are cancelled.
 ///
 /// ## Panics
-/// todo: what happens if an actor panics?
+///
+/// The executor catches panics in any of the actor functions. When a panic is caught, the
+/// on_panic() function is called so that the actor can clean up, calls that are in progress or
+/// waiting in the inbox are answered with [Error::ActorPanicked](crate::Error::ActorPanicked),
+/// and the actor stops with [ExitReason::Panicked](crate::ExitReason::Panicked). Futures that
+/// were registered and that are still active are cancelled. A panic in on_initialization() is
+/// reported as [ExitReason::InitFailed](crate::ExitReason::InitFailed) instead, so that
+/// supervisors and pools do not keep restarting an actor that can not start.
 ///
 pub trait Actor {
     /// The type of messages this actor uses for sends.
@@ -69,17 +90,41 @@ pub trait Actor {
     /// The only restrictions on the messages are that they are Send and Sync, so that they can be
     /// passed between threads and ActorRef can be cloned.
     type SendMessage: Send + Sync + Clone;
-    /// The type of messages this actor uses for calls. These messages include the call itself and
-    /// the result of the call.
+    /// The type of messages this actor uses for calls.
     ///
     /// The only restrictions on the messages are that they are Send and Sync, so that they can be
     /// passed between threads and ActorRef can be cloned.
     type CallMessage: Send + Sync + Clone;
+    /// The type of the replies to calls, returned by handle_calls().
+    ///
+    /// The reply must be Send so that it can be passed back to the caller.
+    type CallReply: Send;
     /// The error type that actor functions return.
     ///
     /// Actor functions will return a std::result::Result<_, ErrorType>. The ErrorType must be Send so that it
     /// can be passed between threads.
     type ErrorType: Send + Sync + Clone;
+    /// The output type of futures that are spawned using [Control::SpawnFutureWithResult].
+    ///
+    /// The output is passed to handle_future() when the future completes. Use () if the actor
+    /// does not need this.
+    type FutureResult: Send + 'static;
+
+    /// Returns the priority of a send message.
+    ///
+    /// Messages with [Priority::High] are handled before any messages with [Priority::Normal] that
+    /// are waiting in the mailbox. The default implementation returns [Priority::Normal] for all
+    /// messages.
+    #[allow(unused)]        // msg is not used in the default
+    fn send_priority(msg: &Self::SendMessage) -> Priority {
+        Priority::Normal
+    }
+
+    /// Returns the priority of a call message, see send_priority().
+    #[allow(unused)]        // msg is not used in the default
+    fn call_priority(msg: &Self::CallMessage) -> Priority {
+        Priority::Normal
+    }
 
     /// This function is called after the actor has started and before message processing.
     ///
@@ -90,7 +135,9 @@ pub trait Actor {
     /// Note that messages from clients can be received while this function is being executed. These
     /// messages will be executed directly after this function has completed.
     ///
-    /// * self_ref - this is an [ActorRef] to the actor. Store this in the struct if needed.
+    /// * self_ref - this is an [ActorRef] to the actor. Store this in the struct if needed. A stored
+    ///   [ActorRef] keeps the actor alive, store a [WeakActorRef](crate::WeakActorRef) using
+    ///   [ActorRef::downgrade()] if the actor should stop when all other references are dropped.
     ///
     /// Implementations can return any of the [Control] instructions. If a [Control::Shutdown] is
     /// returned then the shutdown is queued behind other messages that may have already been received,
@@ -98,7 +145,7 @@ pub trait Actor {
     /// [Control::Terminate] instruction is returned then this does preempt the processing of other
     /// messages.
     #[allow(unused)]
-    fn on_initialization(&mut self, self_ref: ActorRef<Self>) -> impl Future<Output = Control> + Send { async {
+    fn on_initialization(&mut self, self_ref: ActorRef<Self>) -> impl Future<Output = Control<Self::FutureResult>> + Send { async {
         Control::Ok
     }}
 
@@ -107,47 +154,140 @@ pub trait Actor {
     /// This will always need to be overridden but a default is included which logs
     /// a warning and returns ().
     #[allow(unused)]        // msg is not used in the default
-    fn handle_sends(&mut self, msg: Self::SendMessage) -> impl Future<Output = Control> + Send  { async {
+    fn handle_sends(&mut self, msg: Self::SendMessage) -> impl Future<Output = Control<Self::FutureResult>> + Send  { async {
         warn!("unhandled sent message received.");
         Control::Ok
     }}
 
     /// This function handles call messages, which expect an answering message.
     ///
-    /// This will always need to be overridden but a default is included which panics.
-    #[allow(unused)]        // msg is not used in the default
-    fn handle_calls(&mut self, msg: Self::CallMessage) -> impl Future<Output = (Control, std::result::Result<Self::CallMessage, Self::ErrorType>)> + Send { async {
+    /// This will always need to be overridden, unless handle_calls_deferred() is overridden, but a
+    /// default is included which panics.
+    #[allow(unused, clippy::type_complexity)]        // msg is not used in the default
+    fn handle_calls(&mut self, msg: Self::CallMessage) -> impl Future<Output = (Control<Self::FutureResult>, std::result::Result<Self::CallReply, Self::ErrorType>)> + Send { async {
         panic!("unhandled call message received.");
     }}
 
+    /// This function handles call messages and is given a [ReplyToken] to answer the call with.
+    ///
+    /// Override this function instead of handle_calls() if the actor needs to answer some calls
+    /// later, for example after consulting another actor or waiting for I/O. The token can be
+    /// stored and used when handling a later message, or moved into a spawned future. The actor
+    /// continues to handle other messages until the reply is sent.
+    ///
+    /// The default implementation calls handle_calls() and replies immediately.
+    fn handle_calls_deferred(&mut self, msg: Self::CallMessage, reply: ReplyToken<Self>) -> impl Future<Output = Control<Self::FutureResult>> + Send
+    where Self: Send { async move {
+        let (control, result) = self.handle_calls(msg).await;
+        reply.reply(result);
+        control
+    }}
+
+    /// This function handles the output of a future that was spawned using
+    /// [Control::SpawnFutureWithResult].
+    ///
+    /// The output is handled ahead of send and call messages that are waiting in the mailbox.
+    ///
+    /// The default implementation ignores the output.
+    #[allow(unused)]        // result is not used in the default
+    fn handle_future(&mut self, result: Self::FutureResult) -> impl Future<Output = Control<Self::FutureResult>> + Send { async {
+        Control::Ok
+    }}
+
+    /// This function handles notifications that a monitored actor has stopped.
+    ///
+    /// Actors are monitored using [ActorRef::monitor()]. The default implementation ignores
+    /// the notification.
+    #[allow(unused)]        // down is not used in the default
+    fn handle_down(&mut self, down: Down) -> impl Future<Output = Control<Self::FutureResult>> + Send { async {
+        Control::Ok
+    }}
+
+    /// This function is called when a stream that was attached using [ActorRef::attach_stream()]
+    /// has finished.
+    ///
+    /// * id - the id that was returned by [ActorRef::attach_stream()]
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused)]        // id is not used in the default
+    fn on_stream_finished(&mut self, id: StreamId) -> impl Future<Output = Control<Self::FutureResult>> + Send { async {
+        Control::Ok
+    }}
+
     /// This function is called prior to shutdown.
     ///
     /// This function is called first, after which any registered futures are awaited. The return
     /// values from these futures are ignored (handle_future() is not called).
     ///
     /// A [Control] must be returned by this function. [Control::Shutdown], [Control::Terminate] are
-    /// ignored. A [Control::SpawnFuture] will be implemented and the task will be added to the
-    /// list of tasks that are awaited before the shutdown completes.
+    /// ignored. A [Control::SpawnFuture] or [Control::SpawnFutureWithResult] will be implemented and
+    /// the task will be added to the list of tasks that are awaited before the shutdown completes.
     ///
     /// The default implementation does nothing.
-    fn on_shutdown(&mut self) -> impl Future<Output = Control> + Send { async {
+    fn on_shutdown(&mut self) -> impl Future<Output = Control<Self::FutureResult>> + Send { async {
         Control::Ok
     }}
+
+    /// This function is called after one of the other actor functions has panicked.
+    ///
+    /// It can be overridden to release resources that would otherwise be left in an inconsistent
+    /// state. Note that the state of the actor may be only partially updated by the function that
+    /// panicked. The actor stops after this function completes.
+    ///
+    /// * message - the panic message
+    ///
+    /// The default implementation does nothing.
+    #[allow(unused)]        // message is not used in the default
+    fn on_panic(&mut self, message: &str) -> impl Future<Output = ()> + Send { async {
+    }}
 }
 
 
 /// Create an instance of an actor using default configuration.
-pub async fn create_actor<T>(instance: T) -> Result<(ActorRef<T>, JoinHandle<()>)>
+///
+/// The returned [ActorHandle] resolves to the [ExitReason](crate::ExitReason) of the actor once it has stopped.
+pub async fn create_actor<T>(instance: T) -> Result<(ActorRef<T>, ActorHandle<T>)>
+where
+    T: Actor + Send + Sync + 'static
+{
+    create_actor_with_config(instance, ActorConfig::new()).await
+}
+
+/// Create an instance of an actor using the given configuration.
+///
+/// Returns [Error::InvalidConfig] if the configuration is not valid.
+pub async fn create_actor_with_config<T>(instance: T, config: ActorConfig) -> Result<(ActorRef<T>, ActorHandle<T>)>
 where
     T: Actor + Send + Sync + 'static
 {
-    let (outbox, inbox) = tokio::sync::mpsc::channel(DEFAULT_ACTOR_BUFFER_SIZE);
-    let a_ref = ActorRef::<T>::new(outbox);
+    spawn_actor(instance, config)
+}
+
+/// Create and spawn an instance of an actor.
+pub(crate) fn spawn_actor<T>(instance: T, config: ActorConfig) -> Result<(ActorRef<T>, ActorHandle<T>)>
+where
+    T: Actor + Send + Sync + 'static
+{
+    if config.mailbox == MailboxCapacity::Bounded(0) {
+        return Err(Error::InvalidConfig);
+    }
+    let (outbox, inbox) = mailbox(config.mailbox, config.overflow);
+    let terminate_token = match &config.parent_token {
+        Some(t) => t.child_token(),
+        None => CancellationToken::new(),
+    };
+    let a_ref = ActorRef::<T>::new(outbox, terminate_token, config.name);
     let a_clone = a_ref.clone();
-    let j = tokio::spawn( async move {
-        let mut exec = ActorExecutor::new(instance, inbox, a_clone);
+    let guard = ExitGuard { id: a_ref.id(), watchers: a_ref.watchers.clone(), terminate_token: a_ref.terminate_token.clone() };
+    let run = async move {
+        let _guard = guard;
+        let exec = ActorExecutor::new(instance, inbox, a_clone);
         exec.run().await
-    });
-    Ok((a_ref, j))
+    };
+    let j = match config.runtime {
+        Some(runtime) => runtime.spawn(run),
+        None => tokio::spawn(run),
+    };
+    let handle = ActorHandle::new(j, a_ref.id(), a_ref.watchers.clone());
+    Ok((a_ref, handle))
 }
-
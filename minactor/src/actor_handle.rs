@@ -0,0 +1,180 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use tokio::task::{JoinError, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use crate::{Actor, ActorId, ExitReason};
+use crate::executor::panic_message;
+use crate::monitor::Watchers;
+
+
+/// A handle to the task of an actor, returned by [create_actor()](crate::create_actor).
+///
+/// Awaiting the handle waits for the actor to stop and returns its [ExitReason]. Use
+/// [join()](ActorHandle::join) to also get the final state of the actor.
+///
+/// Dropping the handle does not stop the actor.
+pub struct ActorHandle<A>
+where A: Actor
+{
+    /// The task of the actor.
+    join: JoinHandle<(ExitReason, Option<A>)>,
+    /// The id of the actor.
+    id: ActorId,
+    /// Completes when the actor has stopped.
+    stopped: Shared<BoxFuture<'static, ExitReason>>,
+}
+
+impl<A> ActorHandle<A>
+where A: Actor + Send + 'static
+{
+    pub(crate) fn new(join: JoinHandle<(ExitReason, Option<A>)>, id: ActorId, watchers: Arc<Watchers>) -> Self {
+        let stopped = async move {
+            watchers.stopped.cancelled().await;
+            watchers.exit_reason().unwrap_or(ExitReason::Terminated)
+        }.boxed().shared();
+        Self { join, id, stopped }
+    }
+
+    /// Get the id of the actor.
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// Wait for the actor to stop and return its exit reason and the final actor instance.
+    ///
+    /// The instance is None if the actor panicked or its task was aborted.
+    pub async fn join(self) -> (ExitReason, Option<A>) {
+        match self.join.await {
+            Ok(r) => r,
+            Err(e) => (join_error_reason(e), None),
+        }
+    }
+
+    /// Returns true if the task of the actor has finished.
+    pub fn is_finished(&self) -> bool {
+        self.join.is_finished()
+    }
+
+    /// Abort the task of the actor.
+    ///
+    /// Unlike [ActorRef::terminate()](crate::ActorRef::terminate), no further actor functions are
+    /// called. The actor stops with [ExitReason::Terminated] and, as with a termination, actors
+    /// that it started as children, such as those of a [Supervisor](crate::Supervisor), are
+    /// terminated.
+    pub fn abort(&self) {
+        self.join.abort();
+    }
+
+    /// Get a future that completes with the exit reason once the actor has stopped.
+    ///
+    /// The future can be cloned, so any number of tasks can wait for the actor to stop without
+    /// owning the handle.
+    pub fn wait_stopped(&self) -> impl Future<Output = ExitReason> + Clone + Send + 'static {
+        self.stopped.clone()
+    }
+}
+
+impl<A> Future for ActorHandle<A>
+where A: Actor
+{
+    type Output = ExitReason;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.join).poll(cx).map(|r| match r {
+            Ok((reason, _)) => reason,
+            Err(e) => join_error_reason(e),
+        })
+    }
+}
+
+/// The exit reason of an actor whose task did not complete.
+fn join_error_reason(e: JoinError) -> ExitReason {
+    if e.is_panic() {
+        ExitReason::Panicked(panic_message(e.into_panic().as_ref()))
+    } else {
+        ExitReason::Terminated
+    }
+}
+
+/// Notifies the monitors and links of the actor if its task is dropped before the executor has
+/// finished, for example when the task is aborted, and terminates its children.
+pub(crate) struct ExitGuard {
+    pub(crate) id: ActorId,
+    pub(crate) watchers: Arc<Watchers>,
+    pub(crate) terminate_token: CancellationToken,
+}
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        // the executor sets the exit reason when it finishes
+        if self.watchers.exit_reason().is_none() {
+            self.terminate_token.cancel();
+            self.watchers.exited(self.id, &ExitReason::Terminated);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::{create_actor, ChildSpec, Error, ExitReason, Supervisor, SupervisorStrategy};
+    use crate::test_code::tests::{CounterCalls, CounterSends, FailingActor, FailingCalls, FailingReplies, SimpleCounter};
+
+    /// Test that the final state of the actor is returned.
+    #[tokio::test]
+    async fn test_join() {
+        let (actor, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        actor.send(CounterSends::Count).await.unwrap();
+        actor.shutdown().await.unwrap();
+        let (reason, instance) = handle.join().await;
+        assert_eq!(reason, ExitReason::Shutdown);
+        let mut instance = instance.unwrap();
+        assert_eq!(crate::Actor::handle_calls(&mut instance, CounterCalls::GetCount).await.1, Ok(1));
+    }
+
+    /// Test that many tasks can wait for the actor to stop.
+    #[tokio::test]
+    async fn test_wait_stopped() {
+        let (actor, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let waiter = handle.wait_stopped();
+        let tasks: Vec<_> = (0..3).map(|_| tokio::spawn(waiter.clone())).collect();
+        assert!(!handle.is_finished());
+        actor.terminate();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), ExitReason::Terminated);
+        }
+        assert_eq!(handle.await, ExitReason::Terminated);
+    }
+
+    /// Test that an aborted actor is reported as terminated.
+    #[tokio::test]
+    async fn test_abort() {
+        let (actor, handle) = create_actor(FailingActor::new()).await.unwrap();
+        assert_eq!(actor.call(FailingCalls::Ping).await.unwrap(), Ok(FailingReplies::Pong));
+        let waiter = handle.wait_stopped();
+        handle.abort();
+        assert_eq!(handle.await, ExitReason::Terminated);
+        let r = tokio::time::timeout(Duration::from_secs(1), waiter).await;
+        assert_eq!(r, Ok(ExitReason::Terminated));
+    }
+
+    /// Test that aborting a supervisor terminates its children.
+    #[tokio::test]
+    async fn test_abort_supervisor() {
+        let sup = Supervisor::new(SupervisorStrategy::OneForOne)
+            .child(ChildSpec::new("child", FailingActor::new));
+        let (sup_ref, handle) = create_actor(sup).await.unwrap();
+        let child = sup_ref.child::<FailingActor>("child").await.unwrap().unwrap();
+        handle.abort();
+        assert_eq!(handle.await, ExitReason::Terminated);
+        let r = tokio::time::timeout(Duration::from_secs(1), child.watchers.stopped.cancelled()).await;
+        assert!(r.is_ok());
+        let r = child.call(FailingCalls::Ping).await.map_err(Error::from);
+        assert!(matches!(r, Err(Error::ActorStopped { .. })));
+    }
+}
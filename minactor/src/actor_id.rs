@@ -0,0 +1,25 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The next actor id to be allocated.
+static NEXT_ACTOR_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A unique identifier of an actor instance.
+///
+/// Every actor that is created is given a new id, which is shared by all of its [ActorRef](crate::ActorRef)s.
+/// Ids are not reused within a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ActorId(u64);
+
+impl ActorId {
+    /// Allocate a new id.
+    pub(crate) fn next() -> Self {
+        ActorId(NEXT_ACTOR_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor-{}", self.0)
+    }
+}
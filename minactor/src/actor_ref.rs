@@ -1,45 +1,142 @@
-use tokio::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
-use crate::{Actor, Error};
-use crate::result::Result;
+use crate::{Actor, ActorId, Error};
+use crate::result::{ActorInfo, Result, SendError, Stage};
 use crate::executor::ActorSysMsg;
+use crate::mailbox::{MailboxError, MailboxSender};
+use crate::monitor::{Link, Watchers};
 
 /// An ActorRef is a reference to an instance of an actor. It is the main contact point with the
 /// running actor.
 ///
 /// An ActorRef is returned from [create_actor()] and is used to send messages to the actor.
 ///
-/// ActorRefs can be cloned as many times as required and can be sent across threads.
+/// ActorRefs can be cloned as many times as required and can be sent across threads. When all
+/// ActorRefs to an actor have been dropped, the actor shuts down after handling the messages in its
+/// mailbox. Use a [WeakActorRef](crate::WeakActorRef) to refer to an actor without keeping it alive.
 // T is message type and U is error type
 pub struct ActorRef<A>
 where A: Actor + ?Sized
 {
     /// The channel to the actor for sending messages.
-    outbox: Sender<ActorSysMsg<A::SendMessage, A::CallMessage, A::ErrorType>>,
+    pub(crate) outbox: MailboxSender<A>,
     /// [CancellationToken] to terminate the actor.
     pub(crate) terminate_token: CancellationToken,
+    /// The id of the actor.
+    pub(crate) id: ActorId,
+    /// The monitors and links of the actor.
+    pub(crate) watchers: Arc<Watchers>,
+    /// The name of the actor.
+    pub(crate) name: Option<Arc<str>>,
 }
 
-impl<A> ActorRef<A> where A: Actor {
-    pub(crate) fn new(outbox: Sender<ActorSysMsg<A::SendMessage, A::CallMessage, A::ErrorType>>) -> Self {
+impl<A> ActorRef<A> where A: Actor + 'static {
+    pub(crate) fn new(outbox: MailboxSender<A>, terminate_token: CancellationToken, name: Option<String>) -> Self {
         Self {
             outbox,
-            terminate_token: CancellationToken::new(),
+            terminate_token,
+            id: ActorId::next(),
+            watchers: Arc::new(Watchers::new()),
+            name: name.map(Arc::from),
         }
     }
 
+    /// Send a system message to the actor.
+    pub(crate) async fn send_sys(&self, msg: ActorSysMsg<A>) -> Result<()> {
+        self.outbox.send(msg).await.map_err(|e| self.mailbox_error(e).0)
+    }
+
+    /// Information about the actor, for use in errors.
+    pub(crate) fn info(&self) -> ActorInfo {
+        ActorInfo::new(self.id, self.name.clone())
+    }
+
+    /// Get the id of the actor.
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// Get the name of the actor, if it was given one using [ActorConfig::name()](crate::ActorConfig::name).
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Send a message to the actor without expecting a response.
-    pub async fn send(&self, msg: A::SendMessage) -> Result<()> {
-        self.outbox.send(ActorSysMsg::Send(msg)).await.map_err(|_| Error::UnableToSend)?;
-        Ok(())
+    ///
+    /// If the mailbox of the actor is full then the
+    /// [overflow policy](crate::ActorConfig::overflow) of the actor determines what happens.
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError].
+    pub async fn send(&self, msg: A::SendMessage) -> std::result::Result<(), SendError<A::SendMessage>> {
+        self.outbox.send(ActorSysMsg::Send(msg)).await.map_err(|e| self.send_error(e))
     }
 
     /// Send a message to the actor and await a response.
-    pub async fn call(&self, msg: A::CallMessage) -> Result<std::result::Result<A::CallMessage, A::ErrorType>> {
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError].
+    pub async fn call(&self, msg: A::CallMessage) -> std::result::Result<std::result::Result<A::CallReply, A::ErrorType>, SendError<A::CallMessage>> {
+        let (send, recv) = tokio::sync::oneshot::channel();
+        self.outbox.send(ActorSysMsg::Call(msg, send)).await.map_err(|e| self.call_error(e))?;
+        match recv.await {
+            Ok(reply) => reply.map_err(|e| SendError::new(e, None)),
+            Err(_) => Err(SendError::new(Error::UnableToReceive { actor: self.info() }, None)),
+        }
+    }
+
+    /// Send a message to the actor without waiting for space in its mailbox.
+    ///
+    /// Returns [Error::MailboxFull] if there is no space in the mailbox and [Error::ActorStopped] if
+    /// the actor has stopped.
+    pub fn try_send(&self, msg: A::SendMessage) -> std::result::Result<(), SendError<A::SendMessage>> {
+        self.outbox.try_send(ActorSysMsg::Send(msg)).map_err(|e| self.send_error(e))
+    }
+
+    /// Send a message to the actor, waiting at most the given time for space in its mailbox.
+    ///
+    /// Returns [Error::Timeout] if there was no space in time and [Error::ActorStopped] if the
+    /// actor has stopped. In both cases the message is returned in the [SendError].
+    pub async fn send_timeout(&self, msg: A::SendMessage, timeout: Duration) -> std::result::Result<(), SendError<A::SendMessage>> {
+        self.outbox.send_until(ActorSysMsg::Send(msg), Instant::now() + timeout).await
+            .map_err(|e| self.send_error(e))
+    }
+
+    /// Send a message to the actor and await a response for at most the given time.
+    ///
+    /// The time includes waiting for space in the mailbox. Returns [Error::Timeout] if there was
+    /// no response in time and [Error::ActorStopped] if the actor has stopped. If the timeout
+    /// happened while waiting for the reply, see [Error::stage()], then the actor may still handle
+    /// the message, otherwise the message is returned in the [SendError].
+    pub async fn call_timeout(&self, msg: A::CallMessage, timeout: Duration) -> std::result::Result<std::result::Result<A::CallReply, A::ErrorType>, SendError<A::CallMessage>> {
+        self.call_with_deadline(msg, Instant::now() + timeout).await
+    }
+
+    /// Send a message to the actor and await a response until the deadline.
+    ///
+    /// The same as [call_timeout()](Self::call_timeout) but with an absolute deadline, which is
+    /// useful when several calls share a time budget.
+    pub async fn call_with_deadline(&self, msg: A::CallMessage, deadline: Instant) -> std::result::Result<std::result::Result<A::CallReply, A::ErrorType>, SendError<A::CallMessage>> {
         let (send, recv) = tokio::sync::oneshot::channel();
-        self.outbox.send(ActorSysMsg::Call(msg, send)).await.map_err(|_| Error::UnableToSend)?;
-        let reply = recv.await.map_err(|_| Error::UnableToReceive)?;
-        Ok(reply)
+        self.outbox.send_until(ActorSysMsg::Call(msg, send), deadline).await
+            .map_err(|e| self.call_error(e))?;
+        match tokio::time::timeout_at(deadline, recv).await {
+            Ok(Ok(reply)) => reply.map_err(|e| SendError::new(e, None)),
+            Ok(Err(_)) => Err(SendError::new(Error::UnableToReceive { actor: self.info() }, None)),
+            Err(_) => Err(SendError::new(Error::Timeout { actor: self.info(), stage: Stage::AwaitingReply }, None)),
+        }
+    }
+
+    /// The number of messages that are waiting in the mailbox of the actor, not including
+    /// system messages such as shutdown.
+    pub fn mailbox_len(&self) -> usize {
+        self.outbox.len()
+    }
+
+    /// The number of messages that have been dropped because the mailbox of the actor was full,
+    /// see [OverflowPolicy](crate::OverflowPolicy).
+    pub fn dropped_messages(&self) -> u64 {
+        self.outbox.dropped()
     }
 
     /// Shutdown the actor.
@@ -47,9 +144,10 @@ impl<A> ActorRef<A> where A: Actor {
     /// This is a controlled, orderly shutdown. Previous sends and calls will be processed before the
     /// actor is shut down. Subsequent sends and calls will be ignored, which will have no effect
     /// for sends but will produce an error for outstanding calls.
+    ///
+    /// The shutdown instruction does not wait for space in the mailbox of the actor.
     pub async fn shutdown(&self) -> Result<()> {
-        self.outbox.send(ActorSysMsg::Shutdown).await.map_err(|_| Error::UnableToSend)?;
-        Ok(())
+        self.send_sys(ActorSysMsg::Shutdown).await
     }
 
     /// Terminate the actor.
@@ -59,6 +157,81 @@ impl<A> ActorRef<A> where A: Actor {
     pub fn terminate(&self) {
         self.terminate_token.cancel();
     }
+
+    /// Monitor another actor.
+    ///
+    /// When the other actor stops, for whatever reason, a [Down](crate::Down) notification is
+    /// delivered to this actor using [Actor::handle_down()]. If the other actor has already
+    /// stopped, the notification is delivered immediately.
+    pub fn monitor<B>(&self, other: &ActorRef<B>)
+    where
+        A: 'static,
+        B: Actor,
+    {
+        // the monitor does not keep this actor alive
+        let weak = self.downgrade();
+        other.watchers.add_monitor(other.id, Box::new(move |down| {
+            if let Some(actor_ref) = weak.upgrade() {
+                tokio::spawn(async move {
+                    // an error means this actor has stopped and is no longer interested
+                    let _ = actor_ref.send_sys(ActorSysMsg::Down(down)).await;
+                });
+            }
+        }));
+    }
+
+    /// Link this actor with another actor.
+    ///
+    /// Links are bidirectional. If either actor stops abnormally, see
+    /// [ExitReason::is_normal()](crate::ExitReason::is_normal), then the other actor is terminated.
+    /// If the other actor has already stopped abnormally, this actor is terminated.
+    pub fn link<B>(&self, other: &ActorRef<B>)
+    where
+        B: Actor + 'static,
+    {
+        let this_link = Link { id: self.id, watchers: self.watchers.clone(), terminate_token: self.terminate_token.clone() };
+        let other_link = Link { id: other.id, watchers: other.watchers.clone(), terminate_token: other.terminate_token.clone() };
+        if let Some(reason) = self.watchers.add_link(other_link) {
+            if !reason.is_normal() {
+                other.terminate();
+            }
+        }
+        if let Some(reason) = other.watchers.add_link(this_link) {
+            if !reason.is_normal() {
+                self.terminate();
+            }
+        }
+    }
+}
+
+impl<A> ActorRef<A> where A: Actor + 'static {
+    /// Split the error from sending a message to the mailbox into an [Error] and the message.
+    ///
+    /// A closed mailbox is reported as [Error::ActorStopped].
+    pub(crate) fn mailbox_error(&self, e: MailboxError<A>) -> (Error, ActorSysMsg<A>) {
+        let actor = self.info();
+        match e {
+            MailboxError::Full(msg) => (Error::MailboxFull { actor, message: None }, msg),
+            MailboxError::Closed(msg) => (Error::ActorStopped { actor, message: None }, msg),
+            MailboxError::Timeout(msg) => (Error::Timeout { actor, stage: Stage::Enqueue }, msg),
+        }
+    }
+
+    /// Convert the error from sending a send message into a [SendError] containing the message.
+    fn send_error(&self, e: MailboxError<A>) -> SendError<A::SendMessage> {
+        match self.mailbox_error(e) {
+            (error, ActorSysMsg::Send(msg)) => SendError::new(error, Some(msg)),
+            (error, _) => SendError::new(error, None),
+        }
+    }
+
+    /// Convert the error from sending a call message into a [SendError] containing the message.
+    fn call_error(&self, e: MailboxError<A>) -> SendError<A::CallMessage> {
+        match self.mailbox_error(e) {
+            (error, ActorSysMsg::Call(msg, _)) => SendError::new(error, Some(msg)),
+            (error, _) => SendError::new(error, None),
+        }
+    }
 }
 
 impl<A> Clone for ActorRef<A>
@@ -69,6 +242,9 @@ where
         Self {
             outbox: self.outbox.clone(),
             terminate_token: self.terminate_token.clone(),
+            id: self.id,
+            watchers: self.watchers.clone(),
+            name: self.name.clone(),
         }
     }
 }
@@ -76,7 +252,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::create_actor;
+    use crate::{create_actor, create_actor_with_config, ActorConfig, ExitReason};
     use super::*;
     use std::sync::atomic::Ordering;
     use crate::test_code::tests::*;
@@ -101,13 +277,13 @@ mod tests {
         // the shutdown message. Since the system is then shutdown, this will result in an error.
         let r = actor.call(DelayingCalls::DoPong).await;
         assert!(r.is_err());
-        assert_eq!(r, Err(Error::UnableToReceive));
+        assert_eq!(r, Err(SendError::new(Error::UnableToReceive { actor: actor.info() }, None)));
         // although the actor ref struct still exists, it should produce an error when we try to send
         let r = actor.send(DelayingSends::Ping).await;
         assert!(r.is_err());
-        assert_eq!(r, Err(Error::UnableToSend));
+        assert!(matches!(r.map_err(Error::from), Err(Error::ActorStopped { .. })));
         // wait for the actor to finish processing all messages, which should be immediate
-        handle.await.unwrap();
+        handle.await;
         // the counter value should now be 8, showing that the messages were processed
         // before the actor shut down
         let v = COUNTER.load(Ordering::Relaxed);
@@ -121,37 +297,86 @@ mod tests {
         let (actor, handle) = create_actor(instance).await.unwrap();
         let act_clone = actor.clone();
         // confirm that both references have 0
-        if let CounterCalls::Reply(a) = actor.call(CounterCalls::GetCount).await.unwrap().unwrap() {
-            if let CounterCalls::Reply(b) = act_clone.call(CounterCalls::GetCount).await.unwrap().unwrap() {
-                assert_eq!(a, b);
-                assert_eq!(a, 0);
-            } else {
-                assert!(false);
-            }
-        } else {
-            assert!(false);
-        }
+        let a = actor.call(CounterCalls::GetCount).await.unwrap().unwrap();
+        let b = act_clone.call(CounterCalls::GetCount).await.unwrap().unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a, 0);
         // increment original
         let r = actor.send(CounterSends::Count).await;
         assert!(r.is_ok());
         // confirm that both references have 1
-        if let CounterCalls::Reply(a) = actor.call(CounterCalls::GetCount).await.unwrap().unwrap() {
-            if let CounterCalls::Reply(b) = act_clone.call(CounterCalls::GetCount).await.unwrap().unwrap() {
-                assert_eq!(a, b);
-                assert_eq!(a, 1);
-            } else {
-                assert!(false);
-            }
-        } else {
-            assert!(false);
-        }
+        let a = actor.call(CounterCalls::GetCount).await.unwrap().unwrap();
+        let b = act_clone.call(CounterCalls::GetCount).await.unwrap().unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a, 1);
         // shutdown the first ref
         let r = actor.shutdown().await;
         assert!(r.is_ok());
-        let r = handle.await;
-        assert!(r.is_ok());
+        assert_eq!(handle.await, ExitReason::Shutdown);
         // try send to clone, should get error
         let r = act_clone.send(CounterSends::Count).await;
         assert!(r.is_err());
     }
+
+    /// Test try_send with a full mailbox and a stopped actor.
+    #[tokio::test]
+    async fn test_try_send() {
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(1);
+        let (actor, handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        // the actor sleeps while handling the first message, the second fills the mailbox
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(actor.try_send(PrioritySends::Normal(1)), Ok(()));
+        let e = actor.try_send(PrioritySends::Normal(2)).unwrap_err();
+        assert!(matches!(e.error(), Error::MailboxFull { .. }));
+        assert_eq!(e.into_message(), Some(PrioritySends::Normal(2)));
+        actor.shutdown().await.unwrap();
+        handle.await;
+        let e = actor.try_send(PrioritySends::Normal(3)).unwrap_err();
+        assert!(matches!(e.error(), Error::ActorStopped { .. }));
+        assert_eq!(*handled.lock().unwrap(), vec![0, 1]);
+    }
+
+    /// Test send_timeout and call_timeout with a full mailbox and send_timeout with a stopped actor.
+    #[tokio::test]
+    async fn test_send_timeout() {
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(1);
+        let (actor, handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        actor.send(PrioritySends::Normal(1)).await.unwrap();
+        let r = actor.send_timeout(PrioritySends::Normal(2), Duration::from_millis(1)).await;
+        assert_eq!(r, Err(SendError::new(Error::Timeout { actor: actor.info(), stage: Stage::Enqueue }, Some(PrioritySends::Normal(2)))));
+        let r = actor.call_timeout((), Duration::from_millis(1)).await;
+        assert_eq!(r, Err(SendError::new(Error::Timeout { actor: actor.info(), stage: Stage::Enqueue }, Some(()))));
+        let r = actor.send_timeout(PrioritySends::Normal(3), Duration::from_secs(1)).await;
+        assert_eq!(r, Ok(()));
+        actor.shutdown().await.unwrap();
+        handle.await;
+        let r = actor.send_timeout(PrioritySends::Normal(4), Duration::from_secs(1)).await;
+        assert!(matches!(r.map_err(Error::from), Err(Error::ActorStopped { .. })));
+        assert_eq!(*handled.lock().unwrap(), vec![0, 1, 3]);
+    }
+
+    /// Test call_timeout and call_with_deadline with an actor that is busy.
+    #[tokio::test]
+    async fn test_call_timeout() {
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (actor, handle) = create_actor(PriorityActor::new(handled.clone())).await.unwrap();
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        let r = actor.call_timeout((), Duration::from_millis(1)).await;
+        assert_eq!(r.unwrap_err().error(), &Error::Timeout { actor: actor.info(), stage: Stage::AwaitingReply });
+        let r = actor.call_with_deadline((), Instant::now() + Duration::from_secs(1)).await;
+        assert_eq!(r, Ok(Ok(())));
+        actor.shutdown().await.unwrap();
+        handle.await;
+        // a stopped actor is reported as such
+        let r = actor.call_timeout((), Duration::from_secs(1)).await.map_err(Error::from);
+        assert!(matches!(r, Err(Error::ActorStopped { .. })));
+        let r = actor.call_with_deadline((), Instant::now() + Duration::from_secs(1)).await.map_err(Error::from);
+        assert!(matches!(r, Err(Error::ActorStopped { .. })));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use crate::OverflowPolicy;
+
+
+/// The default size of the actor channel buffer. The channel buffers incoming messages, once it is
+/// full then sending threads will wait for space in the buffer.
+const DEFAULT_ACTOR_BUFFER_SIZE: usize = 10;
+
+/// The capacity of the mailbox of an actor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MailboxCapacity {
+    /// The mailbox holds up to the given number of messages.
+    Bounded(usize),
+    /// The mailbox can hold any number of messages.
+    Unbounded,
+}
+
+/// Configuration used when creating an actor with [create_actor_with_config()](crate::create_actor_with_config).
+///
+/// The configuration is built by chaining the setter functions, starting from [ActorConfig::new()]
+/// which has the same configuration that [create_actor()](crate::create_actor) uses.
+///
+/// ```
+/// # use minactor::ActorConfig;
+/// let config = ActorConfig::new()
+///     .name("ingest")
+///     .mailbox_size(1000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ActorConfig {
+    /// The capacity of the mailbox.
+    pub(crate) mailbox: MailboxCapacity,
+    /// What happens when the mailbox is full.
+    pub(crate) overflow: OverflowPolicy,
+    /// The name of the actor.
+    pub(crate) name: Option<String>,
+    /// The runtime on which the actor is spawned, the current runtime if None.
+    pub(crate) runtime: Option<Handle>,
+    /// If set, the actor is terminated when this token is cancelled.
+    pub(crate) parent_token: Option<CancellationToken>,
+}
+
+impl ActorConfig {
+    /// Create the default configuration, which has a bounded mailbox of 10 messages.
+    pub fn new() -> Self {
+        Self {
+            mailbox: MailboxCapacity::Bounded(DEFAULT_ACTOR_BUFFER_SIZE),
+            overflow: OverflowPolicy::Block,
+            name: None,
+            runtime: None,
+            parent_token: None,
+        }
+    }
+
+    /// Set the number of messages that the mailbox can hold, of both priorities together. Once the
+    /// mailbox is full, the [overflow policy](Self::overflow) applies. The size must be greater
+    /// than zero.
+    pub fn mailbox_size(mut self, size: usize) -> Self {
+        self.mailbox = MailboxCapacity::Bounded(size);
+        self
+    }
+
+    /// Use a mailbox that can hold any number of messages. Senders never wait.
+    pub fn unbounded_mailbox(mut self) -> Self {
+        self.mailbox = MailboxCapacity::Unbounded;
+        self
+    }
+
+    /// Set what happens when a message is sent and the mailbox is full. The default is
+    /// [OverflowPolicy::Block], senders wait for space in the mailbox.
+    ///
+    /// The policy applies to send and call messages, it has no effect on an unbounded mailbox.
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    /// Set the name of the actor, see [ActorRef::name()](crate::ActorRef::name).
+    ///
+    /// The name is for identification purposes only, it does not need to be unique. Use
+    /// [register()](crate::register) to make an actor available by name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Spawn the actor on the given runtime instead of the current runtime.
+    pub fn runtime(mut self, runtime: Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Terminate the actor when the token is cancelled.
+    pub(crate) fn parent_token(mut self, token: CancellationToken) -> Self {
+        self.parent_token = Some(token);
+        self
+    }
+}
+
+impl Default for ActorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_actor_with_config, Error};
+    use crate::test_code::tests::{CounterCalls, CounterSends, SimpleCounter};
+    use super::*;
+
+    /// Test that the name is available from the actor reference.
+    #[tokio::test]
+    async fn test_name() {
+        let (actor, _handle) = create_actor_with_config(SimpleCounter::new(false), ActorConfig::new().name("counter")).await.unwrap();
+        assert_eq!(actor.name(), Some("counter"));
+        let (actor, _handle) = create_actor_with_config(SimpleCounter::new(false), ActorConfig::new()).await.unwrap();
+        assert_eq!(actor.name(), None);
+    }
+
+    /// Test that a mailbox size of zero is rejected.
+    #[tokio::test]
+    async fn test_invalid_mailbox_size() {
+        let r = create_actor_with_config(SimpleCounter::new(false), ActorConfig::new().mailbox_size(0)).await;
+        assert!(matches!(r, Err(Error::InvalidConfig)));
+    }
+
+    /// Test that an actor with an unbounded mailbox receives all messages.
+    #[tokio::test]
+    async fn test_unbounded_mailbox() {
+        let (actor, _handle) = create_actor_with_config(SimpleCounter::new(false), ActorConfig::new().unbounded_mailbox()).await.unwrap();
+        for _i in 0..1000 {
+            actor.send(CounterSends::Count).await.unwrap();
+        }
+        assert_eq!(actor.call(CounterCalls::GetCount).await.unwrap(), Ok(1000));
+    }
+}
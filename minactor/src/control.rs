@@ -2,7 +2,11 @@ use std::future::Future;
 use std::pin::Pin;
 
 /// The Control enum is used by an [Actor] to pass instructions the actor executor.
-pub enum Control {
+///
+/// The type parameter is the [Actor::FutureResult](crate::Actor::FutureResult) type of the actor,
+/// it defaults to () so that actors that do not use [Control::SpawnFutureWithResult] can simply
+/// use `Control`.
+pub enum Control<R = ()> {
     /// Processing was completed with no errors and no additional instructions needed.
     Ok,
     /// Initiate Shutdown of the actor. For a description of shutdown, see [Actor].
@@ -11,4 +15,7 @@ pub enum Control {
     Terminate,
     /// Spawn the future as a new task and add to the actor's waitlist.
     SpawnFuture(Pin<Box<dyn Future<Output=()> + Send>>),
+    /// Spawn the future as a new task and add to the actor's waitlist. When the future completes,
+    /// its output is passed to [Actor::handle_future()](crate::Actor::handle_future).
+    SpawnFutureWithResult(Pin<Box<dyn Future<Output=R> + Send>>),
 }
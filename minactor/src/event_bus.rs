@@ -0,0 +1,328 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use crate::{Actor, ActorRef, Error};
+use crate::monitor::MonitorGuard;
+
+
+/// Identifies a subscription to an [EventBus], returned when subscribing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/// Function that delivers an event to a subscriber.
+type Deliver<E> = Arc<dyn Fn(E) -> BoxFuture<'static, Delivery> + Send + Sync>;
+
+/// The outcome of delivering an event to a subscriber.
+enum Delivery {
+    /// The event was delivered.
+    Delivered,
+    /// The event was not delivered, for example because the mailbox of the subscriber is full.
+    Undelivered,
+    /// The subscriber has stopped.
+    Stopped,
+}
+
+/// A typed publish/subscribe event bus.
+///
+/// Actors subscribe to the events that they are interested in, either by topic or using a
+/// predicate, and events are published to the bus. Each event is delivered to the matching
+/// subscribers using [ActorRef::send()], so the send messages of a subscriber must be convertible
+/// from the event type.
+///
+/// The topic of an event is determined by the function given to [EventBus::with_topics()]. A bus
+/// created with [EventBus::new()] has no topics and subscribers use predicates.
+///
+/// The bus does not keep its subscribers alive. A subscriber is unsubscribed automatically when
+/// it stops. The bus can be cloned, all clones share the same subscribers.
+///
+/// ```no_run
+/// # use minactor::{create_actor, Actor, EventBus};
+/// # struct Listener;
+/// # impl Actor for Listener { type SendMessage = String; type CallMessage = (); type CallReply = (); type ErrorType = (); type FutureResult = (); }
+/// # async fn example() {
+/// let bus = EventBus::with_topics(|e: &String| e.split(':').next().unwrap_or_default().to_string());
+/// let (listener, _handle) = create_actor(Listener).await.unwrap();
+/// bus.subscribe_topic(&listener, "orders".to_string());
+/// bus.publish("orders:created".to_string()).await;
+/// # }
+/// ```
+pub struct EventBus<E, T = ()> {
+    inner: Arc<EventBusInner<E, T>>,
+}
+
+/// The state of an event bus, shared by its clones.
+struct EventBusInner<E, T> {
+    /// Determines the topic of an event.
+    topic: Box<dyn Fn(&E) -> T + Send + Sync>,
+    /// The subscriptions.
+    subscriptions: Mutex<Vec<Subscription<E, T>>>,
+    /// The id of the next subscription.
+    next_id: AtomicU64,
+}
+
+/// A subscription to an event bus.
+struct Subscription<E, T> {
+    /// The id of the subscription.
+    id: SubscriptionId,
+    /// Which events the subscriber receives.
+    filter: Filter<E, T>,
+    /// Delivers the events.
+    deliver: Deliver<E>,
+    /// The monitor that removes the subscription when the subscriber stops, None until it has
+    /// been added.
+    monitor: Option<MonitorGuard>,
+}
+
+/// Which events a subscriber receives.
+enum Filter<E, T> {
+    /// Events with the topic.
+    Topic(T),
+    /// Events that match the predicate.
+    Predicate(Box<dyn Fn(&E) -> bool + Send + Sync>),
+}
+
+impl<E> EventBus<E, ()>
+where E: Clone + Send + Sync + 'static
+{
+    /// Create an event bus without topics.
+    pub fn new() -> Self {
+        Self::with_topics(|_| ())
+    }
+}
+
+impl<E> Default for EventBus<E, ()>
+where E: Clone + Send + Sync + 'static
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, T> EventBus<E, T>
+where
+    E: Clone + Send + Sync + 'static,
+    T: PartialEq + Send + Sync + 'static,
+{
+    /// Create an event bus where the topic of an event is determined by the function.
+    pub fn with_topics<F>(topic: F) -> Self
+    where F: Fn(&E) -> T + Send + Sync + 'static
+    {
+        Self {
+            inner: Arc::new(EventBusInner {
+                topic: Box::new(topic),
+                subscriptions: Mutex::new(Vec::new()),
+                next_id: AtomicU64::new(1),
+            }),
+        }
+    }
+
+    /// Subscribe the actor to the events with the topic.
+    pub fn subscribe_topic<A>(&self, actor: &ActorRef<A>, topic: T) -> SubscriptionId
+    where
+        A: Actor + 'static,
+        E: Into<A::SendMessage>,
+    {
+        self.subscribe_with(actor, Filter::Topic(topic))
+    }
+
+    /// Subscribe the actor to the events that match the predicate.
+    pub fn subscribe<A, P>(&self, actor: &ActorRef<A>, predicate: P) -> SubscriptionId
+    where
+        A: Actor + 'static,
+        E: Into<A::SendMessage>,
+        P: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        self.subscribe_with(actor, Filter::Predicate(Box::new(predicate)))
+    }
+
+    /// Remove the subscription. Has no effect if it has already been removed.
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        self.inner.remove(id);
+    }
+
+    /// The number of subscriptions.
+    pub fn subscribers(&self) -> usize {
+        self.inner.subscriptions.lock().unwrap().len()
+    }
+
+    /// Publish the event to the matching subscribers and return the number of subscribers that
+    /// it was delivered to.
+    ///
+    /// The event is delivered to the subscribers in turn, waiting for space in the mailbox of each
+    /// subscriber. A subscriber whose mailbox is full and does not block, see
+    /// [OverflowPolicy](crate::OverflowPolicy), does not receive the event but stays subscribed.
+    pub async fn publish(&self, event: E) -> usize {
+        let topic = (self.inner.topic)(&event);
+        let matching: Vec<(SubscriptionId, Deliver<E>)> = self.inner.subscriptions.lock().unwrap().iter()
+            .filter(|s| match &s.filter {
+                Filter::Topic(t) => *t == topic,
+                Filter::Predicate(p) => p(&event),
+            })
+            .map(|s| (s.id, s.deliver.clone()))
+            .collect();
+        let mut delivered = 0;
+        for (id, deliver) in matching {
+            match deliver(event.clone()).await {
+                Delivery::Delivered => delivered += 1,
+                Delivery::Undelivered => {},
+                Delivery::Stopped => self.inner.remove(id),
+            }
+        }
+        delivered
+    }
+
+    /// Add a subscription for the actor.
+    fn subscribe_with<A>(&self, actor: &ActorRef<A>, filter: Filter<E, T>) -> SubscriptionId
+    where
+        A: Actor + 'static,
+        E: Into<A::SendMessage>,
+    {
+        let id = SubscriptionId(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
+        // the bus does not keep the subscriber alive
+        let weak = actor.downgrade();
+        let deliver: Deliver<E> = Arc::new(move |event: E| {
+            let actor = weak.upgrade();
+            async move {
+                let Some(actor) = actor else {
+                    return Delivery::Stopped;
+                };
+                match actor.send(event.into()).await {
+                    Ok(()) => Delivery::Delivered,
+                    Err(e) => match e.error() {
+                        Error::ActorStopped { .. } => Delivery::Stopped,
+                        _ => Delivery::Undelivered,
+                    },
+                }
+            }.boxed()
+        });
+        self.inner.subscriptions.lock().unwrap().push(Subscription { id, filter, deliver, monitor: None });
+        // remove the subscription when the subscriber stops
+        let inner = Arc::downgrade(&self.inner);
+        MonitorGuard::add(&actor.watchers, actor.id(), Box::new(move |_down| {
+            if let Some(inner) = inner.upgrade() {
+                inner.remove(id);
+            }
+        }), |guard| {
+            let mut subscriptions = self.inner.subscriptions.lock().unwrap();
+            match subscriptions.iter_mut().find(|s| s.id == id) {
+                Some(subscription) => {
+                    subscription.monitor = Some(guard);
+                    None
+                },
+                // the subscription was removed in the meantime
+                None => Some(guard),
+            }
+        });
+        id
+    }
+}
+
+impl<E, T> EventBusInner<E, T> {
+    /// Remove the subscription and its monitor.
+    fn remove(&self, id: SubscriptionId) {
+        let removed = {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            subscriptions.iter().position(|s| s.id == id).map(|i| subscriptions.remove(i))
+        };
+        if let Some(monitor) = removed.and_then(|s| s.monitor) {
+            monitor.remove();
+        }
+    }
+}
+
+impl<E, T> Clone for EventBus<E, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use crate::{create_actor, create_actor_with_config, ActorConfig, ActorRef, OverflowPolicy};
+    use crate::test_code::tests::{PriorityActor, PrioritySends, StreamActor, StreamCalls};
+    use super::*;
+
+    /// Get the sum of the values sent to the stream actor.
+    async fn sum(actor: &ActorRef<StreamActor>) -> u64 {
+        actor.call(StreamCalls::Get).await.unwrap().unwrap().0
+    }
+
+    /// Test that events are delivered to the subscribers by topic and by predicate.
+    #[tokio::test]
+    async fn test_publish() {
+        let bus = EventBus::with_topics(|e: &u64| e % 2);
+        let (even, _even_handle) = create_actor(StreamActor::new()).await.unwrap();
+        let (large, _large_handle) = create_actor(StreamActor::new()).await.unwrap();
+        bus.subscribe_topic(&even, 0);
+        bus.subscribe(&large, |e| *e > 10);
+        assert_eq!(bus.publish(2).await, 1);
+        assert_eq!(bus.publish(11).await, 1);
+        assert_eq!(bus.publish(12).await, 2);
+        assert_eq!(bus.publish(3).await, 0);
+        assert_eq!(sum(&even).await, 14);
+        assert_eq!(sum(&large).await, 23);
+    }
+
+    /// Test that subscriptions can be removed.
+    #[tokio::test]
+    async fn test_unsubscribe() {
+        let bus = EventBus::<u64>::new();
+        let (actor, _handle) = create_actor(StreamActor::new()).await.unwrap();
+        let id = bus.subscribe(&actor, |_| true);
+        assert_eq!(bus.publish(1).await, 1);
+        bus.unsubscribe(id);
+        assert_eq!(bus.subscribers(), 0);
+        assert_eq!(bus.publish(1).await, 0);
+        assert_eq!(sum(&actor).await, 1);
+    }
+
+    /// Test that unsubscribing removes the monitor from the subscriber.
+    #[tokio::test]
+    async fn test_unsubscribe_monitor() {
+        let bus = EventBus::<u64>::new();
+        let (actor, _handle) = create_actor(StreamActor::new()).await.unwrap();
+        for _i in 0..10 {
+            let id = bus.subscribe(&actor, |_| true);
+            bus.unsubscribe(id);
+        }
+        assert_eq!(actor.watchers.monitors(), 0);
+    }
+
+    /// Test that a subscriber with a full mailbox does not receive the event but stays subscribed.
+    #[tokio::test]
+    async fn test_full_subscriber() {
+        let bus = EventBus::<PrioritySends>::new();
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(1).overflow(OverflowPolicy::Reject);
+        let (actor, handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        bus.subscribe(&actor, |_| true);
+        // the actor is busy with the first event, the second fills the mailbox
+        assert_eq!(bus.publish(PrioritySends::Normal(0)).await, 1);
+        tokio::task::yield_now().await;
+        assert_eq!(bus.publish(PrioritySends::Normal(1)).await, 1);
+        assert_eq!(bus.publish(PrioritySends::Normal(2)).await, 0);
+        assert_eq!(bus.subscribers(), 1);
+        actor.shutdown().await.unwrap();
+        handle.await;
+        assert_eq!(*handled.lock().unwrap(), vec![0, 1]);
+    }
+
+    /// Test that subscribers are unsubscribed when they stop.
+    #[tokio::test]
+    async fn test_dead_subscriber() {
+        let bus = EventBus::<u64>::new();
+        let (actor, handle) = create_actor(StreamActor::new()).await.unwrap();
+        bus.subscribe(&actor, |_| true);
+        bus.subscribe(&actor, |e| *e > 10);
+        assert_eq!(bus.subscribers(), 2);
+        actor.shutdown().await.unwrap();
+        handle.await;
+        assert_eq!(bus.subscribers(), 0);
+        assert_eq!(bus.publish(1).await, 0);
+    }
+}
@@ -1,11 +1,26 @@
+use std::any::Any;
 use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
+use std::sync::Arc;
+use futures::{FutureExt, StreamExt};
+use futures::stream::SelectAll;
 use log::warn;
 use tokio::select;
-use tokio::sync::mpsc::Receiver;
 use tokio_util::task::TaskTracker;
-use crate::{Actor, ActorRef};
+use crate::{Actor, ActorRef, Down, Error, WeakActorRef};
 use crate::control::Control;
+use crate::exit_reason::ExitReason;
+use crate::handler::HandlerMessage;
+use crate::mailbox::{Envelope, MailboxReceiver};
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+use crate::MessageKind;
+#[cfg(feature = "metrics")]
+use crate::metrics::Meter;
+#[cfg(feature = "tracing")]
+use crate::trace::handler_span;
+use crate::reply_token::{PendingReplies, ReplyToken};
+use crate::stream::{AttachedStream, StreamEvent};
 
 /// The ActorExecutor executes the actor, receiving messages and forwarding them to handlers.
 pub(crate) struct ActorExecutor<T>
@@ -13,128 +28,475 @@ where T: Actor + Send {
     /// The actor struct.
     instance: T,
     /// Messages are received here.
-    inbox: Receiver<ActorSysMsg<T::SendMessage, T::CallMessage, T::ErrorType>>,
-    /// Reference to the actor.
-    actor_ref: ActorRef<T>,
+    inbox: MailboxReceiver<T>,
+    /// Reference to the actor, passed to the actor during initialization.
+    init_ref: Option<ActorRef<T>>,
+    /// Weak reference to the actor, so that the executor does not keep the mailbox open.
+    actor_ref: WeakActorRef<T>,
     /// Tasks that are being tracked.
     tasks: TaskTracker,
+    /// Streams that have been attached to the actor.
+    streams: SelectAll<AttachedStream<T::SendMessage>>,
+    /// Set when a shutdown has started, to the sequence number of the first message that is discarded.
+    shutdown_at: Option<u64>,
+    /// The exit reason of the actor if it stops without being terminated.
+    stop_reason: ExitReason,
+    /// The calls that are waiting for a reply through a [ReplyToken].
+    replies: Arc<PendingReplies<T>>,
+    /// Set when the actor has panicked, the instance is not returned after this.
+    panicked: bool,
+    /// Records the metrics of the actor.
+    #[cfg(feature = "metrics")]
+    meter: Meter,
 }
 
 impl<T> ActorExecutor<T>
 where
-    T: Actor + Send + Sync
+    T: Actor + Send + Sync + 'static
 {
     /// Create a new instance of the executor.
-    pub(crate) fn new(instance: T, inbox: Receiver<ActorSysMsg<T::SendMessage, T::CallMessage, T::ErrorType>>, actor_ref: ActorRef<T>) -> Self {
+    pub(crate) fn new(instance: T, inbox: MailboxReceiver<T>, actor_ref: ActorRef<T>) -> Self {
         ActorExecutor {
-            instance, inbox, actor_ref, tasks: TaskTracker::new()
+            #[cfg(feature = "metrics")]
+            meter: Meter::new::<T>(&actor_ref.info()),
+            instance, inbox, actor_ref: actor_ref.downgrade(), init_ref: Some(actor_ref), tasks: TaskTracker::new(), streams: SelectAll::new(), shutdown_at: None,
+            stop_reason: ExitReason::Normal, replies: Arc::new(PendingReplies::new()), panicked: false,
+        }
+    }
+
+    /// Run the actor until it stops, then notify its monitors and links.
+    ///
+    /// Returns the exit reason and the actor instance, unless the actor panicked.
+    pub(crate) async fn run(mut self) -> (ExitReason, Option<T>) {
+        let reason = self.execute().await;
+        #[cfg(feature = "metrics")]
+        self.meter.exited(&reason);
+        self.replies.stopped(&self.actor_ref.info());
+        self.actor_ref.watchers.exited(self.actor_ref.id(), &reason);
+        if self.panicked {
+            (reason, None)
+        } else {
+            (reason, Some(self.instance))
         }
     }
 
     /// Executor run loop.
-    pub(crate) async fn run(&mut self) {
-        use ActorSysMsg::*;
-        let r = self.instance.on_initialization(self.actor_ref.clone()).await;
-        match self.handle_control(r).await {
-            Ok(()) => {},
-            _err => {
-                return;
+    async fn execute(&mut self) -> ExitReason {
+        let self_ref = self.init_ref.take().expect("actor executed twice");
+        let r = catch_panic(self.instance.on_initialization(self_ref)).await;
+        let mut outcome = self.after_handler(r);
+        match outcome {
+            Outcome::Stop => return ExitReason::InitFailed,
+            Outcome::Panicked(message) => {
+                // restarting an actor that panics during initialization would most likely fail again
+                self.panicked(message).await;
+                return ExitReason::InitFailed;
             },
+            Outcome::Continue => {},
         }
-        loop {
+        while let Outcome::Continue = outcome {
             // main message processing loop
             select! {
-                _ = self.actor_ref.terminate_token.cancelled() => { break; }
+                _ = self.actor_ref.terminate_token.cancelled() => { outcome = Outcome::Stop; }
                 r = self.inbox.recv() => {
-                    match r {
-                        None => { break; }
-                        Some(sys_msg) => {
-                            match sys_msg {
-                                Shutdown => {
-                                    let r = self.instance.on_shutdown().await;
-                                    match r {
-                                        Control::Ok | Control::Shutdown | Control::Terminate => {},
-                                        Control::SpawnFuture(f) => {
-                                            self.spawn_future(f);
-                                        }
-                                    }
-                                    break;
-                                },
-                                Send(msg) => {
-                                    let r = self.instance.handle_sends(msg).await;
-                                    self.handle_control(r).await;       // todo
-                                },
-                                Call(msg, dest) => {
-                                    let (control, result) = self.instance.handle_calls(msg).await;
-                                    match dest.send(result) {
-                                        Ok(()) => {},
-                                        Err(_) => {
-                                            warn!("unable to send reply of call message to caller.");
-                                        }
-                                    }
-                                    self.handle_control(control).await; // todo
-                                },
-                            }
+                    outcome = match r {
+                        // the user lane of the mailbox has been closed and is empty, either
+                        // due to a shutdown or because all references to the actor have been dropped
+                        None => self.shutdown().await,
+                        Some(envelope) => {
+                            #[cfg(feature = "tracing")]
+                            let span = handler_span(&self.actor_ref.info(), &envelope.span, MessageKind::of(&envelope.msg));
+                            let handled = self.metered_dispatch(envelope);
+                            #[cfg(feature = "tracing")]
+                            let handled = tracing::Instrument::instrument(handled, span);
+                            handled.await
+                        },
+                    };
+                }
+                Some(event) = self.streams.next(), if !self.streams.is_empty() && self.shutdown_at.is_none() => {
+                    #[cfg(feature = "metrics")]
+                    let start = std::time::Instant::now();
+                    #[cfg(feature = "tracing")]
+                    let span = handler_span(&self.actor_ref.info(), &tracing::Span::none(), MessageKind::Send);
+                    let handled = async {
+                        match event {
+                            StreamEvent::Item(msg) => catch_panic(self.instance.handle_sends(msg)).await,
+                            StreamEvent::Finished(id) => catch_panic(self.instance.on_stream_finished(id)).await,
                         }
-                    }
+                    };
+                    #[cfg(feature = "tracing")]
+                    let handled = tracing::Instrument::instrument(handled, span);
+                    let r = handled.await;
+                    outcome = self.after_handler(r);
+                    #[cfg(feature = "metrics")]
+                    self.meter.processed(MessageKind::Send, start, self.tasks.len());
                 }
             }
         }
-        if ! self.actor_ref.terminate_token.is_cancelled() {
-            // if not terminated, then clean up
-            self.tasks.close();
-            if ! self.tasks.is_empty() {
-                self.tasks.wait().await;
-            }
+        // attached streams are not needed anymore
+        self.streams.clear();
+        if let Outcome::Panicked(message) = outcome {
+            return self.panicked(message).await;
+        }
+        if self.actor_ref.terminate_token.is_cancelled() {
+            return ExitReason::Terminated;
+        }
+        // if not terminated, then clean up. The inbox is closed first so that the results of
+        // spawned futures are discarded rather than waiting for space in the inbox.
+        self.inbox.close();
+        self.tasks.close();
+        if ! self.tasks.is_empty() {
+            self.tasks.wait().await;
+        }
+        self.stop_reason.clone()
+    }
+
+    /// Handle a message from the mailbox, recording its metrics.
+    #[cfg(feature = "metrics")]
+    async fn metered_dispatch(&mut self, envelope: Envelope<T>) -> Outcome {
+        let kind = MessageKind::of(&envelope.msg);
+        // messages that are discarded due to a shutdown are not processed
+        let discarded = kind != MessageKind::Control && self.sent_after_shutdown(envelope.seq);
+        self.meter.received(self.inbox.len());
+        let start = std::time::Instant::now();
+        let outcome = self.dispatch(envelope).await;
+        if !discarded {
+            self.meter.processed(kind, start, self.tasks.len());
+        }
+        outcome
+    }
+
+    /// Handle a message from the mailbox.
+    #[cfg(not(feature = "metrics"))]
+    async fn metered_dispatch(&mut self, envelope: Envelope<T>) -> Outcome {
+        self.dispatch(envelope).await
+    }
+
+    /// Handle a message from the mailbox.
+    async fn dispatch(&mut self, envelope: Envelope<T>) -> Outcome {
+        use ActorSysMsg::*;
+        let Envelope { seq, msg, .. } = envelope;
+        match msg {
+            Shutdown => {
+                self.begin_shutdown(seq, ExitReason::Shutdown);
+                Outcome::Continue
+            },
+            AttachStream(stream) => {
+                if self.shutdown_at.is_none() {
+                    self.streams.push(stream);
+                }
+                Outcome::Continue
+            },
+            FutureResult(result) => {
+                let r = catch_panic(self.instance.handle_future(result)).await;
+                self.after_handler(r)
+            },
+            Down(down) => {
+                let r = catch_panic(self.instance.handle_down(down)).await;
+                self.after_handler(r)
+            },
+            Send(msg) => {
+                if self.sent_after_shutdown(seq) {
+                    return Outcome::Continue;
+                }
+                let r = catch_panic(self.instance.handle_sends(msg)).await;
+                self.after_handler(r)
+            },
+            Call(msg, dest) => {
+                if self.sent_after_shutdown(seq) {
+                    // dropping the destination produces an error for the caller
+                    return Outcome::Continue;
+                }
+                let reply = ReplyToken::new(dest, self.actor_ref.info(), self.replies.clone());
+                let r = catch_panic(self.instance.handle_calls_deferred(msg, reply)).await;
+                self.after_handler(r)
+            },
+            Handle(msg) => {
+                if self.sent_after_shutdown(seq) {
+                    // dropping the message produces an error for the caller
+                    return Outcome::Continue;
+                }
+                let r = msg.handle(&mut self.instance, self.actor_ref.info()).await;
+                self.after_handler(r)
+            },
+        }
+    }
+
+    /// Process the result of one of the actor functions.
+    fn after_handler(&mut self, r: std::result::Result<Control<T::FutureResult>, String>) -> Outcome {
+        match r {
+            Ok(control) => match self.handle_control(control) {
+                Ok(()) => Outcome::Continue,
+                Err(_) => Outcome::Stop,
+            },
+            Err(message) => Outcome::Panicked(message),
+        }
+    }
+
+    /// Start a shutdown. Send and call messages with a sequence number below the cutoff are still
+    /// handled, later messages are discarded.
+    fn begin_shutdown(&mut self, cutoff: u64, reason: ExitReason) {
+        if self.shutdown_at.is_none() {
+            self.stop_reason = reason;
+        }
+        self.shutdown_at = Some(self.shutdown_at.map_or(cutoff, |c| c.min(cutoff)));
+        self.inbox.close_user();
+    }
+
+    /// Returns true if the message with the sequence number was sent after a shutdown.
+    fn sent_after_shutdown(&self, seq: u64) -> bool {
+        self.shutdown_at.is_some_and(|cutoff| seq >= cutoff)
+    }
+
+    /// Complete the shutdown, once all messages that were sent before the shutdown have been handled.
+    async fn shutdown(&mut self) -> Outcome {
+        match catch_panic(self.instance.on_shutdown()).await {
+            Ok(Control::Ok | Control::Shutdown | Control::Terminate) => Outcome::Stop,
+            Ok(Control::SpawnFuture(f)) => {
+                self.spawn_future(f);
+                Outcome::Stop
+            },
+            Ok(Control::SpawnFutureWithResult(f)) => {
+                self.spawn_future_with_result(f);
+                Outcome::Stop
+            },
+            Err(message) => Outcome::Panicked(message),
         }
     }
 
     /// Several of the actor methods return a Control message, handle it here.
-    async fn handle_control(&mut self, control: Control) -> crate::result::Result<()> {
+    fn handle_control(&mut self, control: Control<T::FutureResult>) -> crate::result::Result<()> {
         match control {
             Control::Ok => Ok(()),
-            Control::Terminate => Err(crate::result::Error::Terminated),
+            Control::Terminate => {
+                // cancel the token so that the rest of the executor sees the termination
+                self.actor_ref.terminate_token.cancel();
+                Err(crate::result::Error::Terminated)
+            },
             Control::Shutdown => {
-                // queue up a shutdown message
-                self.actor_ref.shutdown().await
+                // messages that have already been sent are handled before the shutdown
+                self.begin_shutdown(self.inbox.next_seq(), ExitReason::Normal);
+                Ok(())
             },
             Control::SpawnFuture(f) => {
                 self.spawn_future(f);
                 Ok(())
+            },
+            Control::SpawnFutureWithResult(f) => {
+                self.spawn_future_with_result(f);
+                Ok(())
+            },
+        }
+    }
+
+    /// Handle a panic in one of the actor functions.
+    ///
+    /// The on_panic() function of the actor is called, and any calls that are waiting in the
+    /// inbox are answered with [Error::ActorPanicked].
+    async fn panicked(&mut self, message: String) -> ExitReason {
+        warn!("actor panicked: {}", message);
+        self.panicked = true;
+        self.replies.set_panicked();
+        if let Err(m) = catch_panic(self.instance.on_panic(&message)).await {
+            warn!("actor panicked in on_panic(): {}", m);
+        }
+        self.inbox.close();
+        while let Some(envelope) = self.inbox.try_recv() {
+            match envelope.msg {
+                ActorSysMsg::Call(_, dest) => {
+                    let _ = dest.send(Err(Error::ActorPanicked { actor: self.actor_ref.info() }));
+                },
+                ActorSysMsg::Handle(msg) => msg.fail(Error::ActorPanicked { actor: self.actor_ref.info() }),
+                _ => {},
             }
         }
+        // the actor is gone, so anything that depends on its termination token must stop as well
+        self.actor_ref.terminate_token.cancel();
+        ExitReason::Panicked(message)
     }
 
     /// Spawn the future into a task and track it.
     fn spawn_future(&mut self, f: Pin<Box<dyn Future<Output=()> + Send>>) {
-        self.tasks.spawn(f);
+        self.track(f);
     }
+
+    /// Spawn the future into a task and track it, the output is sent back to the actor.
+    fn spawn_future_with_result(&mut self, f: Pin<Box<dyn Future<Output=T::FutureResult> + Send>>) {
+        let actor_ref = self.actor_ref.clone();
+        self.track(async move {
+            let result = f.await;
+            // if the actor is shutting down then the result is not needed
+            if let Some(actor_ref) = actor_ref.upgrade() {
+                let _ = actor_ref.send_sys(ActorSysMsg::FutureResult(result)).await;
+            }
+        });
+    }
+
+    /// Spawn a task and track it. The task is cancelled when the actor is terminated or panics.
+    fn track<F>(&mut self, f: F)
+    where F: Future<Output=()> + Send + 'static
+    {
+        let token = self.actor_ref.terminate_token.clone();
+        self.tasks.spawn(async move {
+            select! {
+                biased;
+                _ = token.cancelled() => {},
+                _ = f => {},
+            }
+        });
+    }
+}
+
+
+/// What the run loop does after a message has been handled.
+enum Outcome {
+    /// Continue processing messages.
+    Continue,
+    /// Stop processing messages.
+    Stop,
+    /// One of the actor functions panicked, with the message.
+    Panicked(String),
 }
 
+/// Await the future, catching any panic and returning its message.
+pub(crate) async fn catch_panic<F: Future>(f: F) -> std::result::Result<F::Output, String> {
+    AssertUnwindSafe(f).catch_unwind().await.map_err(|payload| panic_message(payload.as_ref()))
+}
+
+/// Extract the message from a panic payload.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+
+/// The reply to a call message. The outer result is an error if the actor was unable to process
+/// the call.
+pub(crate) type ReplyResult<A> = crate::result::Result<Result<<A as Actor>::CallReply, <A as Actor>::ErrorType>>;
 
 /// Messages to the actor get wrapped in an ActorSysMsg.
-pub(crate) enum ActorSysMsg<S, C, E>
-where S: Send, C: Send, E: Send {
+pub(crate) enum ActorSysMsg<A>
+where A: Actor + ?Sized {
     /// Normal shutdown
     Shutdown,
     /// A send message
-    Send(S),
+    Send(A::SendMessage),
     /// A call message
-    Call(C, tokio::sync::oneshot::Sender<Result<C, E>>),
+    Call(A::CallMessage, tokio::sync::oneshot::Sender<ReplyResult<A>>),
+    /// A message for a [Handler](crate::Handler)
+    Handle(HandlerMessage<A>),
+    /// A monitored actor has stopped
+    Down(Down),
+    /// The output of a future spawned with [Control::SpawnFutureWithResult]
+    FutureResult(A::FutureResult),
+    /// Attach a stream of send messages
+    AttachStream(AttachedStream<A::SendMessage>),
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::create_actor;
-    use crate::test_code::tests::SimpleCounter;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::time::MissedTickBehavior;
+    use crate::{create_actor, Error, ExitReason};
+    use crate::test_code::tests::{eventually, FailingActor, FailingSends, FutureActor, FutureCalls, PanickingActor, PanickingCalls, PanickingSends, PendingActor, ShutdownRecorder, SimpleCounter};
 
     /// Test that the actor shuts down if quit is returned by on_initialization()
     #[tokio::test]
     async fn test_init_quit() {
         let instance = SimpleCounter::new(true);
         let (_actor, handle) = create_actor(instance).await.unwrap();
+        assert_eq!(handle.await, ExitReason::Normal);
+    }
+
+    /// Test that a panic in on_initialization() is reported as a failed initialization.
+    #[tokio::test]
+    async fn test_init_panicked() {
+        let (_actor, handle) = create_actor(FailingActor::panicking_init()).await.unwrap();
+        let (reason, state) = handle.join().await;
+        assert_eq!(reason, ExitReason::InitFailed);
+        assert!(state.is_none());
+    }
+
+    /// Test that the actor stops if it returns Control::Terminate from a handler.
+    #[tokio::test]
+    async fn test_handler_terminate() {
+        let instance = FailingActor::new();
+        let (actor, handle) = create_actor(instance).await.unwrap();
+        actor.send(FailingSends::Fail).await.unwrap();
+        let r = handle.await;
+        assert_eq!(r, ExitReason::Terminated);
+    }
+
+    /// Test that a panic in a call handler is reported to the caller and as the exit reason.
+    #[tokio::test]
+    async fn test_call_panic() {
+        let cleaned_up = Arc::new(AtomicBool::new(false));
+        let instance = PanickingActor::new(cleaned_up.clone());
+        let (actor, handle) = create_actor(instance).await.unwrap();
+        let r = actor.call(PanickingCalls::Panic).await;
+        assert_eq!(r.unwrap_err().error(), &Error::ActorPanicked { actor: actor.info() });
         let r = handle.await;
-        assert!(r.is_ok());
+        assert_eq!(r, ExitReason::Panicked("call panic".to_string()));
+        assert!(cleaned_up.load(Ordering::Relaxed));
     }
-}
\ No newline at end of file
+
+    /// Test that calls queued behind a panicking send receive an error.
+    #[tokio::test]
+    async fn test_send_panic() {
+        let instance = PanickingActor::new(Arc::new(AtomicBool::new(false)));
+        let (actor, handle) = create_actor(instance).await.unwrap();
+        actor.send(PanickingSends::Panic).await.unwrap();
+        let r = actor.call(PanickingCalls::Ping).await;
+        assert!(matches!(r.map_err(Error::from), Err(Error::ActorPanicked { .. } | Error::ActorStopped { .. })));
+        let r = handle.await;
+        assert_eq!(r, ExitReason::Panicked("send panic".to_string()));
+    }
+
+    /// Test that the output of a future spawned with SpawnFutureWithResult is passed to handle_future().
+    #[tokio::test]
+    async fn test_future_result() {
+        let (actor, handle) = create_actor(FutureActor::new()).await.unwrap();
+        actor.send(21).await.unwrap();
+        let value = eventually(|| async { actor.call(FutureCalls::Get).await.unwrap().unwrap() }).await;
+        assert_eq!(value, 42);
+        actor.shutdown().await.unwrap();
+        assert_eq!(handle.await, ExitReason::Shutdown);
+    }
+
+    /// Test that spawned futures are cancelled when the actor is terminated.
+    #[tokio::test]
+    async fn test_terminate_cancels_futures() {
+        let held = Arc::new(());
+        let (actor, handle) = create_actor(PendingActor).await.unwrap();
+        actor.send(held.clone()).await.unwrap();
+        // the call is handled after the send, so the future has been spawned
+        actor.call(()).await.unwrap().unwrap();
+        actor.terminate();
+        assert_eq!(handle.await, ExitReason::Terminated);
+        eventually(|| async { (Arc::strong_count(&held) == 1).then_some(()) }).await;
+    }
+
+    /// Test that the actor shuts down when all references to it have been dropped.
+    #[tokio::test]
+    async fn test_drop_refs() {
+        let shut_down = Arc::new(AtomicBool::new(false));
+        let (actor, handle) = create_actor(ShutdownRecorder::new(shut_down.clone())).await.unwrap();
+        let weak = actor.downgrade();
+        // neither timers nor weak references keep the actor alive
+        let timer = actor.send_interval(Duration::from_millis(1), (), MissedTickBehavior::Skip);
+        actor.send(()).await.unwrap();
+        drop(actor);
+        assert_eq!(handle.await, ExitReason::Normal);
+        assert!(shut_down.load(Ordering::Relaxed));
+        assert!(weak.upgrade().is_none());
+        assert!(!timer.is_active());
+    }
+}
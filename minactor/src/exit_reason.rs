@@ -0,0 +1,69 @@
+/// The reason that an actor stopped executing.
+///
+/// This is the value returned when awaiting the [ActorHandle](crate::ActorHandle) returned by
+/// [create_actor()](crate::create_actor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The actor stopped by itself, either by returning [Control::Shutdown](crate::Control::Shutdown)
+    /// or because all references to it were dropped.
+    Normal,
+    /// The actor was shut down using [ActorRef::shutdown()](crate::ActorRef::shutdown).
+    Shutdown,
+    /// The actor was terminated, either by [ActorRef::terminate()](crate::ActorRef::terminate) or by
+    /// returning [Control::Terminate](crate::Control::Terminate).
+    Terminated,
+    /// The actor returned [Control::Terminate](crate::Control::Terminate) from
+    /// [Actor::on_initialization()](crate::Actor::on_initialization), or panicked in it.
+    InitFailed,
+    /// One of the actor functions panicked, the value is the panic message.
+    Panicked(String),
+}
+
+impl ExitReason {
+    /// Returns true if the actor stopped normally, which includes a shutdown.
+    ///
+    /// Supervisors use this to decide whether a transient child should be restarted.
+    pub fn is_normal(&self) -> bool {
+        matches!(self, ExitReason::Normal | ExitReason::Shutdown)
+    }
+
+    /// The kind of the exit reason, without the panic message.
+    pub fn kind(&self) -> ExitKind {
+        match self {
+            ExitReason::Normal => ExitKind::Normal,
+            ExitReason::Shutdown => ExitKind::Shutdown,
+            ExitReason::Terminated => ExitKind::Terminated,
+            ExitReason::InitFailed => ExitKind::InitFailed,
+            ExitReason::Panicked(_) => ExitKind::Panicked,
+        }
+    }
+}
+
+/// The kind of an [ExitReason].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitKind {
+    /// See [ExitReason::Normal].
+    Normal,
+    /// See [ExitReason::Shutdown].
+    Shutdown,
+    /// See [ExitReason::Terminated].
+    Terminated,
+    /// See [ExitReason::InitFailed].
+    InitFailed,
+    /// See [ExitReason::Panicked].
+    Panicked,
+}
+
+impl ExitKind {
+    /// Index of the kind in per-kind arrays.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn index(self) -> usize {
+        match self {
+            ExitKind::Normal => 0,
+            ExitKind::Shutdown => 1,
+            ExitKind::Terminated => 2,
+            ExitKind::InitFailed => 3,
+            ExitKind::Panicked => 4,
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::any::Any;
+use std::future::Future;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use log::warn;
+use tokio::sync::oneshot;
+use crate::{Actor, ActorRef, Control, Error};
+use crate::executor::{catch_panic, ActorSysMsg};
+use crate::mailbox::MailboxError;
+use crate::result::{ActorInfo, Result, SendError};
+
+
+/// An actor implements `Handler<M>` for each message type M that it handles, in addition to its
+/// [Actor::SendMessage] and [Actor::CallMessage].
+///
+/// This makes it possible to split the interface of an actor over several small message types,
+/// each with its own response type, rather than a single large enum. Messages are sent using
+/// [ActorRef::ask()], which waits for the response, or [ActorRef::tell()], which does not. These
+/// are the equivalents of [ActorRef::call()] and [ActorRef::send()], which remain available for
+/// the call and send messages of the actor.
+///
+/// Handler messages go through the same mailbox as the other messages of the actor and are
+/// handled in order with them.
+///
+/// ```no_run
+/// # use minactor::{create_actor, Actor, Control, Handler};
+/// struct Add(u64);
+/// struct Total;
+///
+/// struct Adder { total: u64 }
+/// # impl Actor for Adder { type SendMessage = (); type CallMessage = (); type CallReply = (); type ErrorType = (); type FutureResult = (); }
+///
+/// impl Handler<Add> for Adder {
+///     type Response = ();
+///
+///     async fn handle(&mut self, msg: Add) -> (Control, ()) {
+///         self.total += msg.0;
+///         (Control::Ok, ())
+///     }
+/// }
+///
+/// impl Handler<Total> for Adder {
+///     type Response = u64;
+///
+///     async fn handle(&mut self, _msg: Total) -> (Control, u64) {
+///         (Control::Ok, self.total)
+///     }
+/// }
+///
+/// # async fn example() {
+/// let (adder, _handle) = create_actor(Adder { total: 0 }).await.unwrap();
+/// adder.tell(Add(2)).await.unwrap();
+/// let total = adder.ask(Total).await.unwrap();
+/// # }
+/// ```
+pub trait Handler<M>: Actor
+where M: Send + 'static
+{
+    /// The type of the response to the message.
+    type Response: Send + 'static;
+
+    /// Handle the message and return the response.
+    ///
+    /// The response is discarded if the message was sent using [ActorRef::tell()].
+    fn handle(&mut self, msg: M) -> impl Future<Output = (Control<Self::FutureResult>, Self::Response)> + Send;
+}
+
+/// A message for a [Handler], with its type erased so that it can be passed through the mailbox.
+pub(crate) struct HandlerMessage<A>(Box<dyn ErasedMessage<A>>)
+where A: Actor + ?Sized;
+
+impl<A> HandlerMessage<A>
+where A: Actor + ?Sized
+{
+    /// Create a new message, the response is sent to reply if it is given.
+    fn new<M>(msg: M, reply: Option<oneshot::Sender<Result<<A as Handler<M>>::Response>>>) -> Self
+    where
+        A: Handler<M> + Send,
+        M: Send + 'static,
+    {
+        Self(Box::new(Request { msg, reply }))
+    }
+
+    /// Handle the message, catching any panic and returning its message.
+    pub(crate) fn handle(self, actor: &mut A, info: ActorInfo) -> BoxFuture<'_, std::result::Result<Control<A::FutureResult>, String>> {
+        self.0.handle(actor, info)
+    }
+
+    /// Answer the sender with the error instead of handling the message.
+    pub(crate) fn fail(self, error: Error) {
+        self.0.fail(error)
+    }
+
+    /// Returns true if the sender is waiting for the response.
+    #[cfg(any(feature = "metrics", feature = "tracing"))]
+    pub(crate) fn wants_reply(&self) -> bool {
+        self.0.wants_reply()
+    }
+
+    /// Get the message back, if it is of type M.
+    fn into_message<M: 'static>(self) -> Option<M> {
+        self.0.into_message().downcast().ok().map(|m| *m)
+    }
+}
+
+/// A handler message and where to send the response.
+struct Request<M, R> {
+    /// The message.
+    msg: M,
+    /// Where to send the response, None if the response is not wanted.
+    reply: Option<oneshot::Sender<Result<R>>>,
+}
+
+/// The operations on a [Request] for which the message type is not needed.
+trait ErasedMessage<A>: Send
+where A: Actor + ?Sized
+{
+    /// Handle the message, see [HandlerMessage::handle()].
+    fn handle<'a>(self: Box<Self>, actor: &'a mut A, info: ActorInfo) -> BoxFuture<'a, std::result::Result<Control<A::FutureResult>, String>>;
+
+    /// Answer the sender with the error, see [HandlerMessage::fail()].
+    fn fail(self: Box<Self>, error: Error);
+
+    /// Get the message back.
+    fn into_message(self: Box<Self>) -> Box<dyn Any>;
+
+    /// Whether the sender is waiting for the response, see [HandlerMessage::wants_reply()].
+    #[cfg(any(feature = "metrics", feature = "tracing"))]
+    fn wants_reply(&self) -> bool;
+}
+
+impl<A, M> ErasedMessage<A> for Request<M, <A as Handler<M>>::Response>
+where
+    A: Handler<M> + Send + ?Sized,
+    M: Send + 'static,
+{
+    fn handle<'a>(self: Box<Self>, actor: &'a mut A, info: ActorInfo) -> BoxFuture<'a, std::result::Result<Control<A::FutureResult>, String>> {
+        let Request { msg, reply } = *self;
+        async move {
+            match catch_panic(Handler::<M>::handle(actor, msg)).await {
+                Ok((control, response)) => {
+                    if let Some(reply) = reply {
+                        if reply.send(Ok(response)).is_err() {
+                            warn!("unable to send response of handler message to caller.");
+                        }
+                    }
+                    Ok(control)
+                },
+                Err(message) => {
+                    if let Some(reply) = reply {
+                        let _ = reply.send(Err(Error::ActorPanicked { actor: info }));
+                    }
+                    Err(message)
+                },
+            }
+        }.boxed()
+    }
+
+    fn fail(self: Box<Self>, error: Error) {
+        if let Some(reply) = self.reply {
+            let _ = reply.send(Err(error));
+        }
+    }
+
+    fn into_message(self: Box<Self>) -> Box<dyn Any> {
+        Box::new(self.msg)
+    }
+
+    #[cfg(any(feature = "metrics", feature = "tracing"))]
+    fn wants_reply(&self) -> bool {
+        self.reply.is_some()
+    }
+}
+
+impl<A> ActorRef<A>
+where A: Actor + Send + 'static
+{
+    /// Send a message to a [Handler] of the actor and await the response.
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError].
+    pub async fn ask<M>(&self, msg: M) -> std::result::Result<<A as Handler<M>>::Response, SendError<M>>
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let (send, recv) = oneshot::channel();
+        self.outbox.send(ActorSysMsg::Handle(HandlerMessage::new(msg, Some(send)))).await
+            .map_err(|e| self.handler_error(e))?;
+        match recv.await {
+            Ok(reply) => reply.map_err(|e| SendError::new(e, None)),
+            Err(_) => Err(SendError::new(Error::UnableToReceive { actor: self.info() }, None)),
+        }
+    }
+
+    /// Send a message to a [Handler] of the actor without waiting for the response.
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError].
+    pub async fn tell<M>(&self, msg: M) -> std::result::Result<(), SendError<M>>
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        self.outbox.send(ActorSysMsg::Handle(HandlerMessage::new(msg, None))).await
+            .map_err(|e| self.handler_error(e))
+    }
+
+    /// Convert the error from sending a handler message into a [SendError] containing the message.
+    fn handler_error<M: 'static>(&self, e: MailboxError<A>) -> SendError<M> {
+        match self.mailbox_error(e) {
+            (error, ActorSysMsg::Handle(msg)) => SendError::new(error, msg.into_message()),
+            (error, _) => SendError::new(error, None),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_actor, Error, ExitReason};
+    use crate::test_code::tests::{Add, Describe, Explode, GetTotal, HandlerActor};
+
+    /// Test that each message type gets its own response type.
+    #[tokio::test]
+    async fn test_ask() {
+        let (actor, handle) = create_actor(HandlerActor::new()).await.unwrap();
+        actor.tell(Add(2)).await.unwrap();
+        assert_eq!(actor.ask(Add(3)).await.unwrap(), ());
+        let total: u64 = actor.ask(GetTotal).await.unwrap();
+        assert_eq!(total, 5);
+        let description: String = actor.ask(Describe).await.unwrap();
+        assert_eq!(description, "total is 5");
+        // the send messages of the actor still work alongside the handlers
+        actor.send(10).await.unwrap();
+        assert_eq!(actor.ask(GetTotal).await.unwrap(), 15);
+        actor.shutdown().await.unwrap();
+        assert_eq!(handle.await, ExitReason::Shutdown);
+    }
+
+    /// Test that the message is returned if the actor has stopped.
+    #[tokio::test]
+    async fn test_ask_stopped() {
+        let (actor, handle) = create_actor(HandlerActor::new()).await.unwrap();
+        actor.shutdown().await.unwrap();
+        handle.await;
+        let e = actor.ask(Add(1)).await.unwrap_err();
+        assert!(matches!(e.error(), Error::ActorStopped { .. }));
+        assert_eq!(e.into_message(), Some(Add(1)));
+    }
+
+    /// Test that a panic in a handler is reported to the caller.
+    #[tokio::test]
+    async fn test_handler_panic() {
+        let (actor, handle) = create_actor(HandlerActor::new()).await.unwrap();
+        let e = actor.ask(Explode).await.unwrap_err();
+        assert!(matches!(e.error(), Error::ActorPanicked { .. }));
+        assert!(matches!(handle.await, ExitReason::Panicked(_)));
+    }
+}
@@ -6,14 +6,58 @@
 //! It is designed for single system implementations, not clusters of systems.
 
 mod actor;
+mod actor_handle;
+mod actor_id;
 mod actor_ref;
+mod config;
 mod control;
+mod event_bus;
 mod executor;
+mod exit_reason;
+mod handler;
+mod mailbox;
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+mod message_kind;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod monitor;
+mod pipe;
+mod pool;
+mod recipient;
+mod registry;
+mod reply_token;
 mod result;
+mod stream;
+mod supervisor;
 mod test_code;
+mod timer;
+#[cfg(feature = "tracing")]
+mod trace;
+mod weak_ref;
 
 
-pub use actor::{Actor, create_actor};
+pub use actor::{Actor, create_actor, create_actor_with_config};
+pub use actor_handle::ActorHandle;
+pub use actor_id::ActorId;
 pub use actor_ref::ActorRef;
+pub use config::ActorConfig;
 pub use control::Control;
-pub use result::Error;
+pub use event_bus::{EventBus, SubscriptionId};
+pub use exit_reason::{ExitKind, ExitReason};
+pub use handler::Handler;
+pub use mailbox::{OverflowPolicy, Priority};
+#[cfg(any(feature = "metrics", feature = "tracing"))]
+pub use message_kind::MessageKind;
+#[cfg(feature = "metrics")]
+pub use metrics::{default_metrics_recorder, set_metrics_recorder, ActorMetrics, ActorRecorder, Histogram, InMemoryRecorder, MetricsRecorder};
+pub use monitor::Down;
+pub use pipe::ask_and_pipe;
+pub use pool::{create_pool, create_pool_with_config, Pool, RoutedMessage, Routing};
+pub use recipient::Recipient;
+pub use registry::{create_named_actor, create_named_actor_with_config, lookup, register, unregister};
+pub use reply_token::ReplyToken;
+pub use result::{ActorInfo, Error, SendError, Stage, Undelivered};
+pub use stream::StreamId;
+pub use supervisor::{ChildSpec, Restart, Supervisor, SupervisorCalls, SupervisorReplies, SupervisorSends, SupervisorStrategy};
+pub use timer::TimerHandle;
+pub use weak_ref::WeakActorRef;
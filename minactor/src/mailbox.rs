@@ -0,0 +1,686 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::select;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Instant;
+use crate::Actor;
+use crate::config::MailboxCapacity;
+use crate::executor::ActorSysMsg;
+
+
+/// The priority of a message, see [Actor::send_priority()] and [Actor::call_priority()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// The message is handled in the order in which it was sent.
+    Normal,
+    /// The message is handled before any messages of normal priority that are waiting in the mailbox.
+    High,
+}
+
+/// What happens when a message is sent to an actor with a full mailbox, see
+/// [ActorConfig::overflow()](crate::ActorConfig::overflow).
+///
+/// Messages that are dropped are counted, see [ActorRef::dropped_messages()](crate::ActorRef::dropped_messages).
+/// If a call message is dropped then the caller receives an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// The sender waits for space in the mailbox.
+    #[default]
+    Block,
+    /// The new message is dropped.
+    DropNewest,
+    /// The oldest message waiting in the mailbox is dropped to make space for the new message.
+    DropOldest,
+    /// The new message is rejected and the sender receives [Error::MailboxFull](crate::Error::MailboxFull).
+    Reject,
+}
+
+/// Create a new mailbox for an actor.
+///
+/// The mailbox has two lanes. Send and call messages use the user lane, which has the configured
+/// capacity and overflow policy. The capacity is shared by the messages of both priorities. All
+/// other messages, such as shutdown instructions, use the system lane which is unbounded so that
+/// these messages are never held up by user traffic.
+pub(crate) fn mailbox<A>(capacity: MailboxCapacity, overflow: OverflowPolicy) -> (MailboxSender<A>, MailboxReceiver<A>)
+where A: Actor + ?Sized {
+    let (system_s, system_r) = mpsc::unbounded_channel();
+    let capacity = match capacity {
+        MailboxCapacity::Bounded(size) => Some(size),
+        MailboxCapacity::Unbounded => None,
+    };
+    let dropped = Arc::new(AtomicU64::new(0));
+    let user = Arc::new(Lane::new(capacity, overflow, dropped.clone()));
+    let next_seq = Arc::new(AtomicU64::new(0));
+    let sender = MailboxSender {
+        system: system_s, user: LaneSender::new(user.clone()), next_seq: next_seq.clone(), dropped,
+    };
+    let receiver = MailboxReceiver {
+        system: system_r, user, next_seq, user_done: false,
+    };
+    (sender, receiver)
+}
+
+/// A message in the mailbox.
+///
+/// Send and call messages are numbered in the order in which they were sent. Other messages
+/// carry the number of the next send or call message, which enables a shutdown to determine which
+/// messages were sent before it.
+pub(crate) struct Envelope<A>
+where A: Actor + ?Sized {
+    /// The sequence number.
+    pub(crate) seq: u64,
+    /// The message.
+    pub(crate) msg: ActorSysMsg<A>,
+    /// The span of the sender when the message was sent.
+    #[cfg(feature = "tracing")]
+    pub(crate) span: tracing::Span,
+}
+
+impl<A> Envelope<A>
+where A: Actor + ?Sized {
+    fn new(seq: u64, msg: ActorSysMsg<A>) -> Self {
+        Self {
+            seq, msg,
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::current(),
+        }
+    }
+}
+
+/// The error returned when a message could not be put in the mailbox, containing the message.
+pub(crate) enum MailboxError<A>
+where A: Actor + ?Sized {
+    /// The mailbox is full.
+    Full(ActorSysMsg<A>),
+    /// The mailbox is closed.
+    Closed(ActorSysMsg<A>),
+    /// There was no space in the mailbox before the deadline.
+    Timeout(ActorSysMsg<A>),
+}
+
+/// The sending side of the mailbox of an actor.
+pub(crate) struct MailboxSender<A>
+where A: Actor + ?Sized {
+    /// The system lane.
+    system: mpsc::UnboundedSender<Envelope<A>>,
+    /// The user lane.
+    user: LaneSender<A>,
+    /// The sequence number of the next send or call message.
+    next_seq: Arc<AtomicU64>,
+    /// The number of messages dropped due to the overflow policy.
+    dropped: Arc<AtomicU64>,
+}
+
+impl<A> MailboxSender<A>
+where A: Actor + ?Sized {
+    /// Send a message to the mailbox. If the lane is full, then the sender waits for space if the
+    /// overflow policy is [OverflowPolicy::Block].
+    ///
+    /// If the message could not be put in the mailbox then it is returned.
+    pub(crate) async fn send(&self, msg: ActorSysMsg<A>) -> Result<(), MailboxError<A>> {
+        match self.route(msg) {
+            Route::System(envelope) => self.system.send(envelope).map_err(|e| MailboxError::Closed(e.0.msg)),
+            Route::User(priority, envelope) => self.user.send(priority, envelope, None).await,
+        }
+    }
+
+    /// Send a message to the mailbox, waiting at most until the deadline for space if the lane is
+    /// full and the overflow policy is [OverflowPolicy::Block].
+    ///
+    /// If the message could not be put in the mailbox, including when the deadline passed, then
+    /// it is returned.
+    pub(crate) async fn send_until(&self, msg: ActorSysMsg<A>, deadline: Instant) -> Result<(), MailboxError<A>> {
+        match self.route(msg) {
+            Route::System(envelope) => self.system.send(envelope).map_err(|e| MailboxError::Closed(e.0.msg)),
+            Route::User(priority, envelope) => self.user.send(priority, envelope, Some(deadline)).await,
+        }
+    }
+
+    /// Send a message to the mailbox without waiting for space.
+    ///
+    /// If the message could not be put in the mailbox then it is returned.
+    pub(crate) fn try_send(&self, msg: ActorSysMsg<A>) -> Result<(), MailboxError<A>> {
+        match self.route(msg) {
+            Route::System(envelope) => self.system.send(envelope).map_err(|e| MailboxError::Closed(e.0.msg)),
+            Route::User(priority, envelope) => self.user.lane.push(priority, envelope).map_err(PushError::into_inner),
+        }
+    }
+
+    /// Create a sender that does not keep the mailbox open.
+    pub(crate) fn downgrade(&self) -> WeakMailboxSender<A> {
+        WeakMailboxSender {
+            system: self.system.downgrade(),
+            user: self.user.lane.clone(),
+            next_seq: self.next_seq.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+
+    /// The number of messages that have been dropped due to the overflow policy.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of messages waiting in the user lane of the mailbox.
+    pub(crate) fn len(&self) -> usize {
+        self.user.lane.len()
+    }
+
+    /// Determine the lane for a message and put it in an envelope.
+    fn route(&self, msg: ActorSysMsg<A>) -> Route<A> {
+        let priority = match &msg {
+            ActorSysMsg::Send(m) => Some(A::send_priority(m)),
+            ActorSysMsg::Call(m, _) => Some(A::call_priority(m)),
+            ActorSysMsg::Handle(_) => Some(Priority::Normal),
+            _ => None,
+        };
+        match priority {
+            None => Route::System(Envelope::new(self.next_seq.load(Ordering::SeqCst), msg)),
+            Some(priority) => Route::User(priority, Envelope::new(self.next_seq.fetch_add(1, Ordering::SeqCst), msg)),
+        }
+    }
+}
+
+impl<A> Clone for MailboxSender<A>
+where A: Actor + ?Sized {
+    fn clone(&self) -> Self {
+        Self {
+            system: self.system.clone(),
+            user: self.user.clone(),
+            next_seq: self.next_seq.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+}
+
+/// A sender for the mailbox that does not keep the mailbox open, see [MailboxSender::downgrade()].
+pub(crate) struct WeakMailboxSender<A>
+where A: Actor + ?Sized {
+    system: mpsc::WeakUnboundedSender<Envelope<A>>,
+    user: Arc<Lane<A>>,
+    next_seq: Arc<AtomicU64>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<A> WeakMailboxSender<A>
+where A: Actor + ?Sized {
+    /// Get a sender for the mailbox, if there are still other senders.
+    pub(crate) fn upgrade(&self) -> Option<MailboxSender<A>> {
+        let system = self.system.upgrade()?;
+        let user = LaneSender::upgrade(&self.user)?;
+        Some(MailboxSender { system, user, next_seq: self.next_seq.clone(), dropped: self.dropped.clone() })
+    }
+}
+
+impl<A> Clone for WeakMailboxSender<A>
+where A: Actor + ?Sized {
+    fn clone(&self) -> Self {
+        Self {
+            system: self.system.clone(),
+            user: self.user.clone(),
+            next_seq: self.next_seq.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+}
+
+/// The lane of the mailbox that a message is sent to.
+enum Route<A>
+where A: Actor + ?Sized {
+    System(Envelope<A>),
+    User(Priority, Envelope<A>),
+}
+
+/// The receiving side of the mailbox of an actor.
+pub(crate) struct MailboxReceiver<A>
+where A: Actor + ?Sized {
+    /// The system lane.
+    system: mpsc::UnboundedReceiver<Envelope<A>>,
+    /// The user lane.
+    user: Arc<Lane<A>>,
+    /// The sequence number of the next send or call message.
+    next_seq: Arc<AtomicU64>,
+    /// Whether the user lane is closed and empty.
+    user_done: bool,
+}
+
+impl<A> MailboxReceiver<A>
+where A: Actor + ?Sized {
+    /// Receive the next message. Messages in the system lane are received first, then messages in
+    /// the user lane, where high priority messages come before normal priority messages.
+    ///
+    /// Returns None once the user lane is closed and empty.
+    pub(crate) async fn recv(&mut self) -> Option<Envelope<A>> {
+        while !self.user_done {
+            select! {
+                biased;
+                Some(envelope) = self.system.recv() => return Some(envelope),
+                r = self.user.recv() => match r {
+                    Some(envelope) => return Some(envelope),
+                    None => self.user_done = true,
+                },
+            }
+        }
+        None
+    }
+
+    /// Receive the next message if there is one available.
+    pub(crate) fn try_recv(&mut self) -> Option<Envelope<A>> {
+        self.system.try_recv().ok()
+            .or_else(|| self.user.pop().item())
+    }
+
+    /// The number of messages waiting in the user lane of the mailbox.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn len(&self) -> usize {
+        self.user.len()
+    }
+
+    /// The sequence number that the next send or call message will get.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.next_seq.load(Ordering::SeqCst)
+    }
+
+    /// Close the user lane. Messages that are already in the lane can still be received, and the
+    /// system lane remains open.
+    pub(crate) fn close_user(&mut self) {
+        self.user.close();
+    }
+
+    /// Close all lanes, messages that are already in the mailbox can still be received.
+    pub(crate) fn close(&mut self) {
+        self.system.close();
+        self.close_user();
+    }
+}
+
+impl<A> Drop for MailboxReceiver<A>
+where A: Actor + ?Sized {
+    fn drop(&mut self) {
+        // the senders keep the user lane alive, so it is closed and emptied here so that further
+        // sends fail and waiting callers are not left without a reply
+        self.close();
+        while self.user.pop().item().is_some() {}
+    }
+}
+
+/// The user lane of the mailbox, a queue of messages for each priority with an optional capacity
+/// that is shared by the queues.
+struct Lane<A>
+where A: Actor + ?Sized {
+    /// The messages in the lane and whether it is closed.
+    state: Mutex<LaneState<A>>,
+    /// The maximum number of messages in the lane, None if unbounded.
+    capacity: Option<usize>,
+    /// What to do when the lane is full.
+    overflow: OverflowPolicy,
+    /// The number of messages dropped due to the overflow policy.
+    dropped: Arc<AtomicU64>,
+    /// The number of senders, the lane is closed when there are none.
+    senders: AtomicUsize,
+    /// Notified when a message is added, the lane is closed or the last sender is dropped.
+    readable: Notify,
+    /// Notified when space becomes available or the lane is closed.
+    writable: Notify,
+}
+
+/// The mutable state of a lane.
+struct LaneState<A>
+where A: Actor + ?Sized {
+    high: VecDeque<Envelope<A>>,
+    normal: VecDeque<Envelope<A>>,
+    closed: bool,
+}
+
+impl<A> LaneState<A>
+where A: Actor + ?Sized {
+    /// The number of messages in the lane.
+    fn len(&self) -> usize {
+        self.high.len() + self.normal.len()
+    }
+
+    /// The queue for messages of the priority.
+    fn queue(&mut self, priority: Priority) -> &mut VecDeque<Envelope<A>> {
+        match priority {
+            Priority::High => &mut self.high,
+            Priority::Normal => &mut self.normal,
+        }
+    }
+
+    /// Remove the message that has been waiting the longest, regardless of its priority.
+    fn pop_oldest(&mut self) -> Option<Envelope<A>> {
+        match (self.high.front(), self.normal.front()) {
+            (Some(h), Some(n)) if n.seq < h.seq => self.normal.pop_front(),
+            (Some(_), _) => self.high.pop_front(),
+            (None, _) => self.normal.pop_front(),
+        }
+    }
+}
+
+/// The error returned when a message can not be pushed onto a lane.
+enum PushError<A>
+where A: Actor + ?Sized {
+    Full(Envelope<A>),
+    Closed(Envelope<A>),
+}
+
+impl<A> PushError<A>
+where A: Actor + ?Sized {
+    fn into_inner(self) -> MailboxError<A> {
+        match self {
+            PushError::Full(e) => MailboxError::Full(e.msg),
+            PushError::Closed(e) => MailboxError::Closed(e.msg),
+        }
+    }
+}
+
+/// The result of popping a message from a lane.
+enum Pop<A>
+where A: Actor + ?Sized {
+    /// A message.
+    Item(Envelope<A>),
+    /// No message is available at the moment.
+    Empty,
+    /// No message is available and no more will arrive.
+    Done,
+}
+
+impl<A> Pop<A>
+where A: Actor + ?Sized {
+    fn item(self) -> Option<Envelope<A>> {
+        match self {
+            Pop::Item(envelope) => Some(envelope),
+            _ => None,
+        }
+    }
+}
+
+impl<A> Lane<A>
+where A: Actor + ?Sized {
+    fn new(capacity: Option<usize>, overflow: OverflowPolicy, dropped: Arc<AtomicU64>) -> Self {
+        Self {
+            state: Mutex::new(LaneState { high: VecDeque::new(), normal: VecDeque::new(), closed: false }),
+            capacity,
+            overflow,
+            dropped,
+            senders: AtomicUsize::new(0),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        }
+    }
+
+    /// Push a message with the priority onto the lane, applying the overflow policy if the lane
+    /// is full.
+    ///
+    /// Returns [PushError::Full] if the lane is full and the policy is to block or reject.
+    fn push(&self, priority: Priority, envelope: Envelope<A>) -> Result<(), PushError<A>> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(PushError::Closed(envelope));
+        }
+        let mut discarded = None;
+        if self.capacity.is_some_and(|c| state.len() >= c) {
+            match self.overflow {
+                OverflowPolicy::Block | OverflowPolicy::Reject => return Err(PushError::Full(envelope)),
+                OverflowPolicy::DropNewest => discarded = Some(envelope),
+                OverflowPolicy::DropOldest => {
+                    discarded = state.pop_oldest();
+                    state.queue(priority).push_back(envelope);
+                },
+            }
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            state.queue(priority).push_back(envelope);
+        }
+        drop(state);
+        // the discarded message is dropped outside of the lock
+        drop(discarded);
+        self.readable.notify_one();
+        Ok(())
+    }
+
+    /// Pop the next message from the lane, high priority messages first.
+    fn pop(&self) -> Pop<A> {
+        let mut state = self.state.lock().unwrap();
+        let next = match state.high.pop_front() {
+            Some(envelope) => Some(envelope),
+            None => state.normal.pop_front(),
+        };
+        match next {
+            Some(envelope) => {
+                drop(state);
+                self.writable.notify_one();
+                Pop::Item(envelope)
+            },
+            None if state.closed || self.senders.load(Ordering::SeqCst) == 0 => Pop::Done,
+            None => Pop::Empty,
+        }
+    }
+
+    /// Receive the next message, waiting for one if the lane is empty.
+    ///
+    /// Returns None once the lane is closed and empty. There must only be one receiver.
+    async fn recv(&self) -> Option<Envelope<A>> {
+        loop {
+            match self.pop() {
+                Pop::Item(envelope) => return Some(envelope),
+                Pop::Done => return None,
+                // the notification is stored if there is no waiter, so it can not be missed
+                Pop::Empty => self.readable.notified().await,
+            }
+        }
+    }
+
+    /// The number of messages in the lane.
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().len()
+    }
+
+    /// Close the lane, messages already in the lane can still be received.
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.writable.notify_waiters();
+    }
+}
+
+/// A sender for a user lane, the lane is closed when all its senders have been dropped.
+struct LaneSender<A>
+where A: Actor + ?Sized {
+    lane: Arc<Lane<A>>,
+}
+
+impl<A> LaneSender<A>
+where A: Actor + ?Sized {
+    fn new(lane: Arc<Lane<A>>) -> Self {
+        lane.senders.fetch_add(1, Ordering::SeqCst);
+        Self { lane }
+    }
+
+    /// Create a sender for the lane, if it still has other senders.
+    fn upgrade(lane: &Arc<Lane<A>>) -> Option<Self> {
+        lane.senders.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n > 0).then_some(n + 1)).ok()?;
+        Some(Self { lane: lane.clone() })
+    }
+
+    /// Send to the lane, waiting for space if the lane is full and the policy is to block.
+    ///
+    /// If there is a deadline and there is no space in the lane by then, the message is returned.
+    async fn send(&self, priority: Priority, mut envelope: Envelope<A>, deadline: Option<Instant>) -> Result<(), MailboxError<A>> {
+        loop {
+            // register interest before trying so that a notification can not be missed
+            let notified = self.lane.writable.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            match self.lane.push(priority, envelope) {
+                Err(PushError::Full(e)) if self.lane.overflow == OverflowPolicy::Block => envelope = e,
+                r => return r.map_err(PushError::into_inner),
+            }
+            match deadline {
+                Some(deadline) => if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    return Err(MailboxError::Timeout(envelope.msg));
+                },
+                None => notified.await,
+            }
+        }
+    }
+}
+
+impl<A> Clone for LaneSender<A>
+where A: Actor + ?Sized {
+    fn clone(&self) -> Self {
+        Self::new(self.lane.clone())
+    }
+}
+
+impl<A> Drop for LaneSender<A>
+where A: Actor + ?Sized {
+    fn drop(&mut self) {
+        if self.lane.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.lane.readable.notify_one();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::{create_actor, create_actor_with_config, ActorConfig, Error, ExitReason, OverflowPolicy};
+    use crate::test_code::tests::{eventually, CounterCalls, CounterSends, PriorityActor, PrioritySends, SimpleCounter};
+
+    /// Wait until the actor has handled the number of messages and return the order.
+    async fn order(handled: &Mutex<Vec<u32>>, count: usize) -> Vec<u32> {
+        eventually(|| async {
+            let handled = handled.lock().unwrap();
+            (handled.len() >= count).then(|| handled.clone())
+        }).await
+    }
+
+    /// Test that high priority messages are handled before waiting normal priority messages.
+    #[tokio::test]
+    async fn test_high_priority() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let (actor, _handle) = create_actor(PriorityActor::new(handled.clone())).await.unwrap();
+        // the actor sleeps while handling the first message, the others wait in the mailbox
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        actor.send(PrioritySends::Normal(1)).await.unwrap();
+        actor.send(PrioritySends::Normal(2)).await.unwrap();
+        actor.send(PrioritySends::Urgent(3)).await.unwrap();
+        assert_eq!(order(&handled, 4).await, vec![0, 3, 1, 2]);
+    }
+
+    /// Test that a shutdown does not wait for space in a full mailbox, and that the messages that
+    /// were sent before it are still handled.
+    #[tokio::test]
+    async fn test_shutdown_full_mailbox() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(2);
+        let (actor, handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        actor.send(PrioritySends::Normal(1)).await.unwrap();
+        actor.send(PrioritySends::Normal(2)).await.unwrap();
+        let r = tokio::time::timeout(Duration::from_millis(10), actor.shutdown()).await;
+        assert!(matches!(r, Ok(Ok(()))));
+        assert_eq!(handle.await, ExitReason::Shutdown);
+        assert_eq!(*handled.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    /// Send four messages to an actor with a mailbox of two messages which is busy with the first,
+    /// then shutdown the actor and return the messages it handled and the number of dropped messages.
+    async fn overflow(policy: OverflowPolicy) -> (Vec<Result<(), Error>>, Vec<u32>, u64) {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(2).overflow(policy);
+        let (actor, handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        let mut results = Vec::new();
+        for i in 1..4 {
+            results.push(actor.send(PrioritySends::Normal(i)).await.map_err(Error::from));
+        }
+        actor.shutdown().await.unwrap();
+        handle.await;
+        let handled = handled.lock().unwrap().clone();
+        (results, handled, actor.dropped_messages())
+    }
+
+    /// Test that the newest message is dropped when the mailbox is full.
+    #[tokio::test]
+    async fn test_drop_newest() {
+        let (results, handled, dropped) = overflow(OverflowPolicy::DropNewest).await;
+        assert_eq!(results, vec![Ok(()), Ok(()), Ok(())]);
+        assert_eq!(handled, vec![0, 1, 2]);
+        assert_eq!(dropped, 1);
+    }
+
+    /// Test that the oldest message is dropped when the mailbox is full.
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let (results, handled, dropped) = overflow(OverflowPolicy::DropOldest).await;
+        assert_eq!(results, vec![Ok(()), Ok(()), Ok(())]);
+        assert_eq!(handled, vec![0, 2, 3]);
+        assert_eq!(dropped, 1);
+    }
+
+    /// Test that a message is rejected when the mailbox is full.
+    #[tokio::test]
+    async fn test_reject() {
+        let (results, handled, dropped) = overflow(OverflowPolicy::Reject).await;
+        assert!(matches!(results[..], [Ok(()), Ok(()), Err(Error::MailboxFull { .. })]));
+        assert_eq!(handled, vec![0, 1, 2]);
+        assert_eq!(dropped, 0);
+    }
+
+    /// Test that the capacity of the mailbox is shared by messages of both priorities.
+    #[tokio::test]
+    async fn test_shared_capacity() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(2).overflow(OverflowPolicy::Reject);
+        let (actor, _handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        actor.try_send(PrioritySends::Normal(1)).unwrap();
+        actor.try_send(PrioritySends::Urgent(2)).unwrap();
+        let r = actor.try_send(PrioritySends::Urgent(3)).map_err(Error::from);
+        assert!(matches!(r, Err(Error::MailboxFull { .. })));
+        assert_eq!(actor.mailbox_len(), 2);
+    }
+
+    /// Test that the oldest message is dropped regardless of its priority.
+    #[tokio::test]
+    async fn test_drop_oldest_priority() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(2).overflow(OverflowPolicy::DropOldest);
+        let (actor, _handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        actor.send(PrioritySends::Normal(1)).await.unwrap();
+        actor.send(PrioritySends::Urgent(2)).await.unwrap();
+        actor.send(PrioritySends::Normal(3)).await.unwrap();
+        assert_eq!(order(&handled, 3).await, vec![0, 2, 3]);
+        assert_eq!(actor.dropped_messages(), 1);
+    }
+
+    /// Test that senders wait for space when the mailbox is full.
+    #[tokio::test]
+    async fn test_block() {
+        let (results, handled, dropped) = overflow(OverflowPolicy::Block).await;
+        assert_eq!(results, vec![Ok(()), Ok(()), Ok(())]);
+        assert_eq!(handled, vec![0, 1, 2, 3]);
+        assert_eq!(dropped, 0);
+    }
+
+    /// Test that messages can not be sent to a terminated actor.
+    #[tokio::test]
+    async fn test_terminated() {
+        let (actor, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        actor.terminate();
+        assert_eq!(handle.await, ExitReason::Terminated);
+        let r = actor.call(CounterCalls::GetCount).await.map_err(Error::from);
+        assert!(matches!(r, Err(Error::ActorStopped { .. })));
+        assert!(actor.send(CounterSends::Count).await.is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::Actor;
+use crate::executor::ActorSysMsg;
+
+
+/// The kind of a message that an actor processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    /// A send message, including items from attached streams and messages sent with
+    /// [ActorRef::tell()](crate::ActorRef::tell).
+    Send,
+    /// A call message, including messages sent with [ActorRef::ask()](crate::ActorRef::ask).
+    Call,
+    /// Any other message, such as a shutdown, a [Down](crate::Down) notification or the output of
+    /// a spawned future.
+    Control,
+}
+
+impl MessageKind {
+    /// Index of the kind in per-kind arrays.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn index(self) -> usize {
+        match self {
+            MessageKind::Send => 0,
+            MessageKind::Call => 1,
+            MessageKind::Control => 2,
+        }
+    }
+
+    /// The name of the kind, as used in tracing spans.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageKind::Send => "send",
+            MessageKind::Call => "call",
+            MessageKind::Control => "control",
+        }
+    }
+
+    /// The kind of the message.
+    pub(crate) fn of<A>(msg: &ActorSysMsg<A>) -> Self
+    where A: Actor + ?Sized
+    {
+        match msg {
+            ActorSysMsg::Send(_) => MessageKind::Send,
+            ActorSysMsg::Call(_, _) => MessageKind::Call,
+            ActorSysMsg::Handle(msg) if msg.wants_reply() => MessageKind::Call,
+            ActorSysMsg::Handle(_) => MessageKind::Send,
+            _ => MessageKind::Control,
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use crate::{ActorInfo, ExitKind, ExitReason, MessageKind};
+
+
+/// The upper bounds of the buckets of a [Histogram], the last bucket has no upper bound.
+const BUCKET_BOUNDS: [Duration; 7] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+/// Records metrics about actors. The recorder is set using [set_metrics_recorder()], until then
+/// the [default recorder](default_metrics_recorder) is used.
+///
+/// Each metric is labelled with the name of the actor, or its type if it has no name, so that the
+/// number of labels stays bounded. Actors with the same label share their metrics.
+pub trait MetricsRecorder: Send + Sync {
+    /// Get the recorder for the metrics of the actor with the label. This is called once when an
+    /// actor is created, the actor then records its metrics using the returned recorder.
+    fn actor(&self, actor: &str) -> Arc<dyn ActorRecorder>;
+
+    /// The actor was restarted by a [Supervisor](crate::Supervisor) or a [Pool](crate::Pool).
+    fn restarted(&self, actor: &str);
+}
+
+/// Records the metrics of an actor, see [MetricsRecorder::actor()].
+///
+/// The recorder is called from the executor of the actor for every message, so its functions
+/// should be quick.
+pub trait ActorRecorder: Send + Sync {
+    /// The actor processed a message, the latency is the time that its handler took.
+    fn message_processed(&self, kind: MessageKind, latency: Duration);
+
+    /// The number of messages waiting in the mailbox of the actor, recorded when the actor
+    /// receives a message.
+    fn mailbox_depth(&self, depth: usize);
+
+    /// The number of futures spawned by the actor that have not completed yet.
+    fn spawned_futures(&self, count: usize);
+
+    /// The actor stopped.
+    fn exited(&self, reason: &ExitReason);
+}
+
+/// The metrics of an actor, as recorded by an [InMemoryRecorder].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActorMetrics {
+    /// The handler latencies, by message kind.
+    latency: [Histogram; 3],
+    /// The last recorded mailbox depth.
+    mailbox_depth: usize,
+    /// The last recorded number of spawned futures.
+    spawned_futures: usize,
+    /// The number of restarts.
+    restarts: u64,
+    /// The number of times that the actor stopped, by kind of exit reason.
+    exits: [u64; 5],
+}
+
+impl ActorMetrics {
+    /// The number of messages of the kind that have been processed.
+    pub fn processed(&self, kind: MessageKind) -> u64 {
+        self.latency(kind).count()
+    }
+
+    /// The histogram of handler latencies for messages of the kind.
+    pub fn latency(&self, kind: MessageKind) -> &Histogram {
+        &self.latency[kind.index()]
+    }
+
+    /// The most recently recorded number of messages waiting in the mailbox.
+    pub fn mailbox_depth(&self) -> usize {
+        self.mailbox_depth
+    }
+
+    /// The most recently recorded number of spawned futures that have not completed.
+    pub fn spawned_futures(&self) -> usize {
+        self.spawned_futures
+    }
+
+    /// The number of times that the actor has been restarted.
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    /// The number of times that the actor stopped with an exit reason of the kind.
+    pub fn exits(&self, kind: ExitKind) -> u64 {
+        self.exits[kind.index()]
+    }
+}
+
+/// A histogram of durations, with buckets from 10µs to 10s that increase by a factor of 10.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    /// The number of durations in each bucket.
+    counts: [u64; BUCKET_BOUNDS.len() + 1],
+    /// The total of the durations.
+    sum: Duration,
+    /// The longest duration.
+    max: Duration,
+}
+
+impl Histogram {
+    /// The number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The total of the durations recorded.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// The longest duration recorded.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// The buckets of the histogram, as pairs of the upper bound of the bucket and the number of
+    /// durations in it. The upper bound of the last bucket is None.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        BUCKET_BOUNDS.iter().map(|b| Some(*b)).chain(std::iter::once(None)).zip(self.counts.iter().copied())
+    }
+}
+
+/// A [MetricsRecorder] that keeps the metrics in memory so that they can be queried, this is the
+/// default recorder.
+///
+/// The recorder can be cloned, all clones share the same metrics.
+///
+/// ```no_run
+/// # use minactor::{default_metrics_recorder, MessageKind};
+/// let metrics = default_metrics_recorder().get("ingest").unwrap_or_default();
+/// println!("ingest processed {} sends", metrics.processed(MessageKind::Send));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRecorder {
+    /// The metrics by actor label.
+    actors: Arc<Mutex<HashMap<String, Arc<SharedMetrics>>>>,
+}
+
+impl InMemoryRecorder {
+    /// Create a recorder without any metrics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the metrics of the actor, if any have been recorded.
+    pub fn get(&self, actor: &str) -> Option<ActorMetrics> {
+        self.actors.lock().unwrap().get(actor).map(|m| m.snapshot())
+    }
+
+    /// The labels of the actors that have metrics.
+    pub fn actors(&self) -> Vec<String> {
+        self.actors.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Reset all metrics to zero. Actors that are running keep recording their metrics.
+    pub fn clear(&self) {
+        for metrics in self.actors.lock().unwrap().values() {
+            metrics.reset();
+        }
+    }
+
+    /// Get the metrics of the actor, adding them if they do not exist yet.
+    fn entry(&self, actor: &str) -> Arc<SharedMetrics> {
+        self.actors.lock().unwrap().entry(actor.to_string()).or_default().clone()
+    }
+}
+
+impl MetricsRecorder for InMemoryRecorder {
+    fn actor(&self, actor: &str) -> Arc<dyn ActorRecorder> {
+        self.entry(actor)
+    }
+
+    fn restarted(&self, actor: &str) {
+        self.entry(actor).restarts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The metrics of an actor in an [InMemoryRecorder], updated by its executor without locking.
+#[derive(Debug, Default)]
+struct SharedMetrics {
+    /// The handler latencies, by message kind.
+    latency: [SharedHistogram; 3],
+    /// The last recorded mailbox depth.
+    mailbox_depth: AtomicUsize,
+    /// The last recorded number of spawned futures.
+    spawned_futures: AtomicUsize,
+    /// The number of restarts.
+    restarts: AtomicU64,
+    /// The number of times that the actor stopped, by kind of exit reason.
+    exits: [AtomicU64; 5],
+}
+
+impl SharedMetrics {
+    /// Get a copy of the current metrics.
+    fn snapshot(&self) -> ActorMetrics {
+        ActorMetrics {
+            latency: self.latency.each_ref().map(|h| h.snapshot()),
+            mailbox_depth: self.mailbox_depth.load(Ordering::Relaxed),
+            spawned_futures: self.spawned_futures.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            exits: self.exits.each_ref().map(|n| n.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Reset the metrics to zero.
+    fn reset(&self) {
+        self.latency.iter().for_each(|h| h.reset());
+        self.mailbox_depth.store(0, Ordering::Relaxed);
+        self.spawned_futures.store(0, Ordering::Relaxed);
+        self.restarts.store(0, Ordering::Relaxed);
+        self.exits.iter().for_each(|n| n.store(0, Ordering::Relaxed));
+    }
+}
+
+impl ActorRecorder for SharedMetrics {
+    fn message_processed(&self, kind: MessageKind, latency: Duration) {
+        self.latency[kind.index()].record(latency);
+    }
+
+    fn mailbox_depth(&self, depth: usize) {
+        self.mailbox_depth.store(depth, Ordering::Relaxed);
+    }
+
+    fn spawned_futures(&self, count: usize) {
+        self.spawned_futures.store(count, Ordering::Relaxed);
+    }
+
+    fn exited(&self, reason: &ExitReason) {
+        self.exits[reason.kind().index()].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A [Histogram] that can be updated without locking, the durations are kept in nanoseconds.
+#[derive(Debug, Default)]
+struct SharedHistogram {
+    /// The number of durations in each bucket.
+    counts: [AtomicU64; BUCKET_BOUNDS.len() + 1],
+    /// The total of the durations.
+    sum: AtomicU64,
+    /// The longest duration.
+    max: AtomicU64,
+}
+
+impl SharedHistogram {
+    /// Add a duration to the histogram.
+    fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(duration)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Get a copy of the histogram.
+    fn snapshot(&self) -> Histogram {
+        Histogram {
+            counts: self.counts.each_ref().map(|n| n.load(Ordering::Relaxed)),
+            sum: Duration::from_nanos(self.sum.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(self.max.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Reset the histogram to zero.
+    fn reset(&self) {
+        self.counts.iter().for_each(|n| n.store(0, Ordering::Relaxed));
+        self.sum.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The index of the bucket of a [Histogram] for the duration.
+fn bucket(duration: Duration) -> usize {
+    BUCKET_BOUNDS.iter().position(|b| duration <= *b).unwrap_or(BUCKET_BOUNDS.len())
+}
+
+/// The default recorder.
+static DEFAULT_RECORDER: OnceLock<InMemoryRecorder> = OnceLock::new();
+
+/// The recorder that is in use.
+static RECORDER: OnceLock<RwLock<Arc<dyn MetricsRecorder>>> = OnceLock::new();
+
+/// Get the [InMemoryRecorder] that records metrics until [set_metrics_recorder()] is called.
+pub fn default_metrics_recorder() -> InMemoryRecorder {
+    DEFAULT_RECORDER.get_or_init(InMemoryRecorder::new).clone()
+}
+
+/// Set the recorder for metrics.
+///
+/// Actors use the recorder that was set when they were created, as do supervisors and pools when
+/// they record restarts, so this is normally called before any actors are created.
+pub fn set_metrics_recorder(recorder: impl MetricsRecorder + 'static) {
+    *recorder_lock().write().unwrap() = Arc::new(recorder);
+}
+
+/// The lock around the recorder that is in use.
+fn recorder_lock() -> &'static RwLock<Arc<dyn MetricsRecorder>> {
+    RECORDER.get_or_init(|| RwLock::new(Arc::new(default_metrics_recorder())))
+}
+
+/// The recorder that is in use.
+pub(crate) fn recorder() -> Arc<dyn MetricsRecorder> {
+    recorder_lock().read().unwrap().clone()
+}
+
+/// The label in metrics of an actor with the name and type.
+pub(crate) fn label(name: Option<&str>, type_name: &str) -> String {
+    name.unwrap_or(type_name).to_string()
+}
+
+/// Records the metrics of an actor from its executor.
+pub(crate) struct Meter {
+    /// The recorder of the actor, from the recorder that was in use when the actor was created.
+    recorder: Arc<dyn ActorRecorder>,
+}
+
+impl Meter {
+    pub(crate) fn new<A: ?Sized>(actor: &ActorInfo) -> Self {
+        Self {
+            recorder: recorder().actor(&label(actor.name(), std::any::type_name::<A>())),
+        }
+    }
+
+    /// Record the mailbox depth when a message is received.
+    pub(crate) fn received(&self, depth: usize) {
+        self.recorder.mailbox_depth(depth);
+    }
+
+    /// Record that a message has been processed by a handler that started at the given time,
+    /// along with the number of spawned futures after the handler.
+    pub(crate) fn processed(&self, kind: MessageKind, start: Instant, futures: usize) {
+        self.recorder.message_processed(kind, start.elapsed());
+        self.recorder.spawned_futures(futures);
+    }
+
+    /// Record that the actor has stopped.
+    pub(crate) fn exited(&self, reason: &ExitReason) {
+        self.recorder.exited(reason);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::{create_actor, create_actor_with_config, create_pool_with_config, ActorConfig, Routing};
+    use crate::test_code::tests::{eventually, CounterCalls, CounterSends, FutureActor, PanickingActor, PanickingSends, SimpleCounter};
+    use super::*;
+
+    /// Get the metrics of the actor from the default recorder.
+    fn metrics(actor: &str) -> ActorMetrics {
+        default_metrics_recorder().get(actor).unwrap()
+    }
+
+    /// Test that messages are counted by kind and that the exit reason is recorded.
+    #[tokio::test]
+    async fn test_processed() {
+        let config = ActorConfig::new().name("metrics-processed");
+        let (actor, handle) = create_actor_with_config(SimpleCounter::new(false), config).await.unwrap();
+        actor.send(CounterSends::Count).await.unwrap();
+        actor.send(CounterSends::Count).await.unwrap();
+        actor.call(CounterCalls::GetCount).await.unwrap().unwrap();
+        actor.shutdown().await.unwrap();
+        assert_eq!(handle.await, ExitReason::Shutdown);
+        let m = metrics("metrics-processed");
+        assert_eq!(m.processed(MessageKind::Send), 2);
+        assert_eq!(m.processed(MessageKind::Call), 1);
+        assert_eq!(m.processed(MessageKind::Control), 1);
+        assert_eq!(m.latency(MessageKind::Send).buckets().map(|(_, n)| n).sum::<u64>(), 2);
+        assert_eq!(m.exits(ExitKind::Shutdown), 1);
+        assert_eq!(m.exits(ExitKind::Normal), 0);
+    }
+
+    /// Test that actors without a name are labelled with their type.
+    #[tokio::test]
+    async fn test_unnamed() {
+        let (actor, _handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        actor.send(CounterSends::Count).await.unwrap();
+        actor.call(CounterCalls::GetCount).await.unwrap().unwrap();
+        let m = metrics(std::any::type_name::<SimpleCounter>());
+        assert!(m.processed(MessageKind::Send) >= 1);
+        assert!(!default_metrics_recorder().actors().contains(&actor.id().to_string()));
+    }
+
+    /// Wait until the actor has processed the number of messages of the kind.
+    async fn wait_for(actor: &str, kind: MessageKind, n: u64) -> ActorMetrics {
+        eventually(|| async {
+            default_metrics_recorder().get(actor).filter(|m| m.processed(kind) >= n)
+        }).await
+    }
+
+    /// Test that spawned futures and their results are recorded.
+    #[tokio::test]
+    async fn test_spawned_futures() {
+        let config = ActorConfig::new().name("metrics-futures");
+        let (actor, _handle) = create_actor_with_config(FutureActor::new(), config).await.unwrap();
+        actor.send(21).await.unwrap();
+        // the future sleeps, so it is still running after the send has been handled
+        let m = wait_for("metrics-futures", MessageKind::Send, 1).await;
+        assert_eq!(m.spawned_futures(), 1);
+        let m = wait_for("metrics-futures", MessageKind::Control, 1).await;
+        assert_eq!(m.processed(MessageKind::Send), 1);
+    }
+
+    /// Test that restarts of pool workers are recorded.
+    #[tokio::test]
+    async fn test_restarts() {
+        let config = ActorConfig::new().name("metrics-worker");
+        let pool = create_pool_with_config(1, Routing::RoundRobin, config, || PanickingActor::new(Default::default())).await.unwrap();
+        pool.send(PanickingSends::Panic).await.unwrap();
+        eventually(|| async { (pool.restarts() == 1).then_some(()) }).await;
+        let m = metrics("metrics-worker");
+        assert_eq!(m.restarts(), 1);
+        assert_eq!(m.exits(ExitKind::Panicked), 1);
+    }
+
+    /// Test that actors with the same label share their metrics, and that they keep recording
+    /// after the metrics are cleared.
+    #[test]
+    fn test_shared() {
+        let recorder = InMemoryRecorder::new();
+        let first = recorder.actor("shared");
+        let second = recorder.actor("shared");
+        first.message_processed(MessageKind::Send, Duration::from_millis(1));
+        second.message_processed(MessageKind::Send, Duration::from_millis(1));
+        second.exited(&ExitReason::Panicked("panic".to_string()));
+        let m = recorder.get("shared").unwrap();
+        assert_eq!(m.processed(MessageKind::Send), 2);
+        assert_eq!(m.exits(ExitKind::Panicked), 1);
+        recorder.clear();
+        first.message_processed(MessageKind::Call, Duration::from_millis(1));
+        let m = recorder.get("shared").unwrap();
+        assert_eq!(m.processed(MessageKind::Send), 0);
+        assert_eq!(m.processed(MessageKind::Call), 1);
+    }
+
+    /// Test the histogram buckets.
+    #[test]
+    fn test_histogram() {
+        let shared = SharedHistogram::default();
+        shared.record(Duration::from_micros(5));
+        shared.record(Duration::from_millis(5));
+        shared.record(Duration::from_secs(20));
+        let h = shared.snapshot();
+        assert_eq!(h.count(), 3);
+        assert_eq!(h.max(), Duration::from_secs(20));
+        let counts: Vec<u64> = h.buckets().map(|(_, n)| n).collect();
+        assert_eq!(counts, vec![1, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(h.buckets().last(), Some((None, 1)));
+    }
+}
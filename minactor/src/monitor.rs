@@ -0,0 +1,221 @@
+use std::sync::{Arc, Mutex, Weak};
+use tokio_util::sync::CancellationToken;
+use crate::{ActorId, ExitReason};
+
+
+/// Notification that a monitored actor has stopped.
+///
+/// This is delivered to the monitoring actor using [Actor::handle_down()](crate::Actor::handle_down),
+/// see [ActorRef::monitor()](crate::ActorRef::monitor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Down {
+    /// The id of the actor that stopped.
+    pub id: ActorId,
+    /// Why the actor stopped.
+    pub reason: ExitReason,
+}
+
+/// Function that delivers a [Down] notification to a monitoring actor.
+pub(crate) type DeliverDown = Box<dyn FnOnce(Down) + Send>;
+
+/// Identifies a monitor that has been added to [Watchers], so that it can be removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MonitorKey(u64);
+
+/// A monitor that removes something, such as a registration, when the watched actor stops.
+///
+/// The guard is kept with the thing that the monitor removes. When that is removed in another
+/// way, the guard removes the monitor so that monitors do not build up on long running actors.
+/// The guard does not keep the watched actor alive.
+pub(crate) struct MonitorGuard {
+    watchers: Weak<Watchers>,
+    key: MonitorKey,
+}
+
+impl MonitorGuard {
+    /// Add a monitor to the actor and pass its guard to `keep`, which stores it.
+    ///
+    /// The monitor is added while the caller holds no locks because it is notified immediately
+    /// if the actor has already stopped. If the thing that the monitor removes has already been
+    /// removed by the time `keep` is called, `keep` returns the guard and the monitor is removed.
+    pub(crate) fn add<F>(watchers: &Arc<Watchers>, id: ActorId, deliver: DeliverDown, keep: F)
+    where F: FnOnce(MonitorGuard) -> Option<MonitorGuard>
+    {
+        let key = watchers.add_monitor(id, deliver);
+        if let Some(guard) = keep(MonitorGuard { watchers: Arc::downgrade(watchers), key }) {
+            guard.remove();
+        }
+    }
+
+    /// Remove the monitor. Has no effect if the monitor has already been notified.
+    pub(crate) fn remove(&self) {
+        if let Some(watchers) = self.watchers.upgrade() {
+            watchers.remove_monitor(self.key);
+        }
+    }
+}
+
+/// The monitors and links of an actor. This is shared by all the references to the actor and is
+/// notified by the executor when the actor stops.
+pub(crate) struct Watchers {
+    inner: Mutex<WatchersInner>,
+    /// Cancelled when the actor has stopped.
+    pub(crate) stopped: CancellationToken,
+}
+
+struct WatchersInner {
+    /// The reason the actor stopped, None while it is running.
+    exit_reason: Option<ExitReason>,
+    /// Monitoring actors that are notified when the actor stops.
+    monitors: Vec<(MonitorKey, DeliverDown)>,
+    /// The key of the next monitor.
+    next_key: u64,
+    /// Linked actors that are terminated if the actor stops abnormally.
+    links: Vec<Link>,
+}
+
+/// A link to another actor.
+pub(crate) struct Link {
+    /// The id of the linked actor.
+    pub(crate) id: ActorId,
+    /// The watchers of the linked actor.
+    pub(crate) watchers: Arc<Watchers>,
+    /// The termination token of the linked actor.
+    pub(crate) terminate_token: CancellationToken,
+}
+
+impl Watchers {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Mutex::new(WatchersInner { exit_reason: None, monitors: Vec::new(), next_key: 0, links: Vec::new() }),
+            stopped: CancellationToken::new(),
+        }
+    }
+
+    /// Add a monitor. If the actor has already stopped, the notification is delivered immediately.
+    ///
+    /// Returns the key that removes the monitor, see [Watchers::remove_monitor()].
+    pub(crate) fn add_monitor(&self, id: ActorId, deliver: DeliverDown) -> MonitorKey {
+        let mut inner = self.inner.lock().unwrap();
+        let key = MonitorKey(inner.next_key);
+        inner.next_key += 1;
+        match &inner.exit_reason {
+            None => inner.monitors.push((key, deliver)),
+            Some(reason) => {
+                let reason = reason.clone();
+                drop(inner);
+                deliver(Down { id, reason });
+            },
+        }
+        key
+    }
+
+    /// Remove the monitor without notifying it. Has no effect if the monitor has already been
+    /// notified.
+    pub(crate) fn remove_monitor(&self, key: MonitorKey) {
+        self.inner.lock().unwrap().monitors.retain(|(k, _)| *k != key);
+    }
+
+    /// Add a link. If the actor has already stopped, the reason is returned and the link is not added.
+    pub(crate) fn add_link(&self, link: Link) -> Option<ExitReason> {
+        let mut inner = self.inner.lock().unwrap();
+        match &inner.exit_reason {
+            None => {
+                if !inner.links.iter().any(|l| l.id == link.id) {
+                    inner.links.push(link);
+                }
+                None
+            },
+            Some(reason) => Some(reason.clone()),
+        }
+    }
+
+    /// Remove the link to the actor with the given id.
+    pub(crate) fn remove_link(&self, id: ActorId) {
+        self.inner.lock().unwrap().links.retain(|l| l.id != id);
+    }
+
+    /// The number of monitors that have not been notified.
+    #[cfg(test)]
+    pub(crate) fn monitors(&self) -> usize {
+        self.inner.lock().unwrap().monitors.len()
+    }
+
+    /// The reason the actor stopped, None while it is running.
+    pub(crate) fn exit_reason(&self) -> Option<ExitReason> {
+        self.inner.lock().unwrap().exit_reason.clone()
+    }
+
+    /// The actor has stopped, notify the monitors and linked actors. Only the first call has an effect.
+    pub(crate) fn exited(&self, id: ActorId, reason: &ExitReason) {
+        let (monitors, links) = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.exit_reason.is_some() {
+                return;
+            }
+            inner.exit_reason = Some(reason.clone());
+            (std::mem::take(&mut inner.monitors), std::mem::take(&mut inner.links))
+        };
+        self.stopped.cancel();
+        for (_key, deliver) in monitors {
+            deliver(Down { id, reason: reason.clone() });
+        }
+        for link in links {
+            link.watchers.remove_link(id);
+            if !reason.is_normal() {
+                link.terminate_token.cancel();
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::{create_actor, Down, ExitReason};
+    use crate::test_code::tests::{eventually, DownRecorder, FailingActor, FailingSends};
+
+    /// Wait until the recorder has received the number of notifications.
+    async fn wait_for_downs(downs: &Mutex<Vec<Down>>, count: usize) {
+        eventually(|| async { (downs.lock().unwrap().len() >= count).then_some(()) }).await
+    }
+
+    /// Test that a monitoring actor is notified when the monitored actor stops.
+    #[tokio::test]
+    async fn test_monitor() {
+        let downs = Arc::new(Mutex::new(Vec::new()));
+        let (watcher, _handle) = create_actor(DownRecorder::new(downs.clone())).await.unwrap();
+        let (target, target_handle) = create_actor(FailingActor::new()).await.unwrap();
+        watcher.monitor(&target);
+        target.send(FailingSends::Fail).await.unwrap();
+        assert_eq!(target_handle.await, ExitReason::Terminated);
+        wait_for_downs(&downs, 1).await;
+        assert_eq!(downs.lock().unwrap()[0], Down { id: target.id(), reason: ExitReason::Terminated });
+        // monitoring an actor that has already stopped delivers the notification immediately
+        watcher.monitor(&target);
+        wait_for_downs(&downs, 2).await;
+    }
+
+    /// Test that an abnormal exit terminates linked actors.
+    #[tokio::test]
+    async fn test_link_abnormal_exit() {
+        let (a, a_handle) = create_actor(FailingActor::new()).await.unwrap();
+        let (b, b_handle) = create_actor(FailingActor::new()).await.unwrap();
+        a.link(&b);
+        b.send(FailingSends::Fail).await.unwrap();
+        assert_eq!(b_handle.await, ExitReason::Terminated);
+        assert_eq!(a_handle.await, ExitReason::Terminated);
+    }
+
+    /// Test that a normal exit does not stop linked actors.
+    #[tokio::test]
+    async fn test_link_normal_exit() {
+        let (a, _a_handle) = create_actor(FailingActor::new()).await.unwrap();
+        let (b, b_handle) = create_actor(FailingActor::new()).await.unwrap();
+        b.link(&a);
+        b.send(FailingSends::Stop).await.unwrap();
+        assert_eq!(b_handle.await, ExitReason::Normal);
+        assert!(a.call(crate::test_code::tests::FailingCalls::Ping).await.is_ok());
+    }
+}
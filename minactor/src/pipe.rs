@@ -0,0 +1,84 @@
+use crate::{Actor, ActorRef, Control, SendError};
+
+
+/// Call another actor without waiting for the reply, the reply is piped back to the calling actor.
+///
+/// This is used from inside the handler of an actor, by returning the [Control] that this function
+/// produces. The call is made in a future that is spawned and tracked like
+/// [Control::SpawnFutureWithResult]. When the call completes, its result is passed to `map` and
+/// the output is passed to [Actor::handle_future()] of the calling actor. Errors, such as the
+/// target having stopped, are also passed to `map`.
+///
+/// The calling actor continues to handle other messages while the call is in progress, so calls
+/// between actors that call each other do not deadlock.
+///
+/// ```no_run
+/// # use minactor::{ask_and_pipe, Actor, ActorRef, Control};
+/// # struct Counter;
+/// # impl Actor for Counter { type SendMessage = (); type CallMessage = (); type CallReply = u64; type ErrorType = (); type FutureResult = (); }
+/// struct Reporter { counter: ActorRef<Counter> }
+///
+/// impl Actor for Reporter {
+///     type SendMessage = ();
+///     type CallMessage = ();
+///     type CallReply = ();
+///     type ErrorType = ();
+///     type FutureResult = Option<u64>;
+///
+///     async fn handle_sends(&mut self, _msg: ()) -> Control<Option<u64>> {
+///         ask_and_pipe(&self.counter, (), |r| r.ok().and_then(|r| r.ok()))
+///     }
+///
+///     async fn handle_future(&mut self, count: Option<u64>) -> Control<Option<u64>> {
+///         println!("count is {:?}", count);
+///         Control::Ok
+///     }
+/// }
+/// ```
+pub fn ask_and_pipe<B, R, F>(target: &ActorRef<B>, msg: B::CallMessage, map: F) -> Control<R>
+where
+    B: Actor + 'static,
+    R: Send + 'static,
+    F: FnOnce(Result<Result<B::CallReply, B::ErrorType>, SendError<B::CallMessage>>) -> R + Send + 'static,
+{
+    let target = target.clone();
+    Control::SpawnFutureWithResult(Box::pin(async move {
+        map(target.call(msg).await)
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::create_actor;
+    use crate::test_code::tests::{eventually, AskingActor, CounterSends, SimpleCounter};
+
+    /// Get the results of the asking actor, once there are the given number.
+    async fn results(actor: &crate::ActorRef<AskingActor>, n: usize) -> Vec<Option<u64>> {
+        eventually(|| async {
+            let results = actor.call(()).await.unwrap().unwrap();
+            (results.len() >= n).then_some(results)
+        }).await
+    }
+
+    /// Test that the reply to the call is piped back to the actor.
+    #[tokio::test]
+    async fn test_ask_and_pipe() {
+        let (counter, _counter_handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        counter.send(CounterSends::Count).await.unwrap();
+        let (actor, _handle) = create_actor(AskingActor::new(counter.clone())).await.unwrap();
+        actor.send(()).await.unwrap();
+        assert_eq!(results(&actor, 1).await, vec![Some(1)]);
+    }
+
+    /// Test that an error from the call is piped back to the actor.
+    #[tokio::test]
+    async fn test_ask_and_pipe_stopped() {
+        let (counter, counter_handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let (actor, _handle) = create_actor(AskingActor::new(counter.clone())).await.unwrap();
+        counter.shutdown().await.unwrap();
+        counter_handle.await;
+        actor.send(()).await.unwrap();
+        assert_eq!(results(&actor, 1).await, vec![None]);
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use log::warn;
+use crate::{Actor, ActorConfig, ActorId, ActorRef, Error, ExitReason, SendError};
+#[cfg(feature = "metrics")]
+use crate::MetricsRecorder;
+use crate::actor::spawn_actor;
+use crate::result::Result;
+
+
+/// How a [Pool] chooses the worker that handles a message.
+pub enum Routing<A>
+where A: Actor
+{
+    /// The workers are used in turn.
+    RoundRobin,
+    /// The worker with the fewest messages waiting in its mailbox is used.
+    ShortestMailbox,
+    /// A worker is chosen at random.
+    Random,
+    /// The worker is chosen using the hash of a key that is extracted from the message, so that
+    /// messages with the same key are handled by the same worker. When the pool is resized, only
+    /// the keys of the workers that were added or removed move to a different worker.
+    ///
+    /// Use [Routing::consistent_hash()] to create this routing.
+    #[allow(clippy::type_complexity)]
+    ConsistentHash(Box<dyn Fn(RoutedMessage<'_, A>) -> u64 + Send + Sync>),
+}
+
+impl<A> Routing<A>
+where A: Actor
+{
+    /// Route messages by the hash of the key that the function extracts from the message.
+    pub fn consistent_hash<K, F>(key: F) -> Self
+    where
+        K: Hash,
+        F: Fn(RoutedMessage<'_, A>) -> K + Send + Sync + 'static,
+    {
+        Routing::ConsistentHash(Box::new(move |msg| {
+            // the default hasher is used because it hashes the same way in every pool
+            let mut hasher = DefaultHasher::new();
+            key(msg).hash(&mut hasher);
+            hasher.finish()
+        }))
+    }
+}
+
+/// A message that is being routed by a [Pool], passed to the key function of
+/// [Routing::ConsistentHash].
+pub enum RoutedMessage<'a, A>
+where A: Actor
+{
+    /// A send message.
+    Send(&'a A::SendMessage),
+    /// A call message.
+    Call(&'a A::CallMessage),
+}
+
+/// A pool of identical worker actors behind a single entry point.
+///
+/// Sends and calls to the pool are passed to one of the workers, chosen using the [Routing] of
+/// the pool. The pool can be resized, and a worker that stops is replaced by a new instance
+/// created by the factory function of the pool. A worker that failed to initialize, or that
+/// could not be replaced, is removed from the pool.
+///
+/// Pools are created using [create_pool()] or [create_pool_with_config()] and can be cloned.
+/// When all clones have been dropped, the workers shut down.
+pub struct Pool<A>
+where A: Actor
+{
+    inner: Arc<PoolInner<A>>,
+}
+
+/// The state of a pool, shared by its clones and the tasks that watch the workers.
+struct PoolInner<A>
+where A: Actor
+{
+    /// Creates the workers.
+    factory: Box<dyn Fn() -> A + Send + Sync>,
+    /// The configuration used to create the workers.
+    config: ActorConfig,
+    /// How the workers are chosen.
+    routing: Routing<A>,
+    /// The workers.
+    workers: Mutex<Vec<ActorRef<A>>>,
+    /// Counter used for choosing workers.
+    next: AtomicUsize,
+    /// The number of workers that have been restarted.
+    restarts: AtomicU64,
+    /// Set when the pool has been shut down, workers are not restarted after this.
+    stopped: AtomicBool,
+    /// The recorder for restarts, which was in use when the pool was created.
+    #[cfg(feature = "metrics")]
+    recorder: Arc<dyn MetricsRecorder>,
+}
+
+/// Create a pool of actors using default configuration for the workers.
+///
+/// The factory function is called to create each worker. Returns [Error::InvalidConfig] if the
+/// size is zero.
+pub async fn create_pool<A, F>(size: usize, routing: Routing<A>, factory: F) -> Result<Pool<A>>
+where
+    A: Actor + Send + Sync + 'static,
+    F: Fn() -> A + Send + Sync + 'static,
+{
+    create_pool_with_config(size, routing, ActorConfig::new(), factory).await
+}
+
+/// Create a pool of actors using the given configuration for the workers.
+///
+/// Returns [Error::InvalidConfig] if the size is zero or the configuration is not valid.
+pub async fn create_pool_with_config<A, F>(size: usize, routing: Routing<A>, config: ActorConfig, factory: F) -> Result<Pool<A>>
+where
+    A: Actor + Send + Sync + 'static,
+    F: Fn() -> A + Send + Sync + 'static,
+{
+    if size == 0 {
+        return Err(Error::InvalidConfig);
+    }
+    let inner = Arc::new(PoolInner {
+        factory: Box::new(factory),
+        config,
+        routing,
+        workers: Mutex::new(Vec::with_capacity(size)),
+        next: AtomicUsize::new(0),
+        restarts: AtomicU64::new(0),
+        stopped: AtomicBool::new(false),
+        #[cfg(feature = "metrics")]
+        recorder: crate::metrics::recorder(),
+    });
+    {
+        let mut workers = inner.workers.lock().unwrap();
+        for _i in 0..size {
+            workers.push(inner.start_worker()?);
+        }
+    }
+    Ok(Pool { inner })
+}
+
+impl<A> Pool<A>
+where A: Actor + Send + Sync + 'static
+{
+    /// Send a message to one of the workers.
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError]. Returns
+    /// [Error::NoWorkers] if all the workers have been removed from the pool.
+    pub async fn send(&self, msg: A::SendMessage) -> std::result::Result<(), SendError<A::SendMessage>> {
+        let Some(worker) = self.inner.choose(RoutedMessage::Send(&msg)) else {
+            return Err(SendError::new(Error::NoWorkers, Some(msg)));
+        };
+        worker.send(msg).await
+    }
+
+    /// Send a message to one of the workers and await a response.
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError]. Returns
+    /// [Error::NoWorkers] if all the workers have been removed from the pool.
+    pub async fn call(&self, msg: A::CallMessage) -> std::result::Result<std::result::Result<A::CallReply, A::ErrorType>, SendError<A::CallMessage>> {
+        let Some(worker) = self.inner.choose(RoutedMessage::Call(&msg)) else {
+            return Err(SendError::new(Error::NoWorkers, Some(msg)));
+        };
+        worker.call(msg).await
+    }
+
+    /// The number of workers in the pool.
+    pub fn size(&self) -> usize {
+        self.inner.workers.lock().unwrap().len()
+    }
+
+    /// Get references to the current workers.
+    pub fn workers(&self) -> Vec<ActorRef<A>> {
+        self.inner.workers.lock().unwrap().clone()
+    }
+
+    /// The number of workers that have been replaced because they stopped.
+    pub fn restarts(&self) -> u64 {
+        self.inner.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Change the number of workers in the pool.
+    ///
+    /// New workers are added at the end. If the pool shrinks, then the last workers are removed
+    /// and shut down after they have handled the messages in their mailboxes. Returns
+    /// [Error::InvalidConfig] if the size is zero.
+    pub async fn resize(&self, size: usize) -> Result<()> {
+        if size == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        let removed = {
+            let mut workers = self.inner.workers.lock().unwrap();
+            while workers.len() < size {
+                workers.push(self.inner.start_worker()?);
+            }
+            workers.split_off(size)
+        };
+        for worker in removed {
+            // an error means the worker has already stopped
+            let _ = worker.shutdown().await;
+        }
+        Ok(())
+    }
+
+    /// Shutdown all the workers, see [ActorRef::shutdown()]. The workers are no longer restarted.
+    pub async fn shutdown(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        for worker in self.workers() {
+            let _ = worker.shutdown().await;
+        }
+    }
+
+    /// Terminate all the workers, see [ActorRef::terminate()]. The workers are no longer restarted.
+    pub fn terminate(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        for worker in self.workers() {
+            worker.terminate();
+        }
+    }
+}
+
+impl<A> Clone for Pool<A>
+where A: Actor
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<A> PoolInner<A>
+where A: Actor + Send + Sync + 'static
+{
+    /// Start a new worker and watch it, so that it can be replaced when it stops.
+    fn start_worker(self: &Arc<Self>) -> Result<ActorRef<A>> {
+        let (worker, handle) = spawn_actor((self.factory)(), self.config.clone())?;
+        let id = worker.id();
+        // the watcher does not keep the pool alive
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let reason = handle.await;
+            if let Some(pool) = pool.upgrade() {
+                pool.replace_worker(id, reason);
+            }
+        });
+        Ok(worker)
+    }
+
+    /// Replace the worker that has stopped, or remove it from the pool if it can not be replaced.
+    fn replace_worker(self: &Arc<Self>, id: ActorId, reason: ExitReason) {
+        if self.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut workers = self.workers.lock().unwrap();
+        // workers that were removed by a resize are not replaced
+        let Some(idx) = workers.iter().position(|w| w.id() == id) else {
+            return;
+        };
+        if reason == ExitReason::InitFailed {
+            // restarting would most likely fail again
+            warn!("pool worker {} failed to initialize, removing it.", id);
+            workers.remove(idx);
+            return;
+        }
+        match self.start_worker() {
+            Ok(worker) => {
+                #[cfg(feature = "metrics")]
+                self.recorder.restarted(&crate::metrics::label(self.config.name.as_deref(), std::any::type_name::<A>()));
+                workers[idx] = worker;
+                self.restarts.fetch_add(1, Ordering::Relaxed);
+            },
+            Err(e) => {
+                warn!("unable to restart pool worker {}, removing it: {}", id, e);
+                workers.remove(idx);
+            },
+        }
+    }
+
+    /// Choose the worker for the message, None if the pool has no workers.
+    fn choose(&self, msg: RoutedMessage<'_, A>) -> Option<ActorRef<A>> {
+        let workers = self.workers.lock().unwrap();
+        let n = workers.len();
+        if n == 0 {
+            return None;
+        }
+        let idx = match &self.routing {
+            Routing::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % n,
+            Routing::ShortestMailbox => {
+                // start at a different worker each time so that ties are shared out
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..n).map(|i| (start + i) % n)
+                    .min_by_key(|i| workers[*i].mailbox_len())
+                    .unwrap_or(0)
+            },
+            Routing::Random => {
+                let r = RandomState::new().hash_one(self.next.fetch_add(1, Ordering::Relaxed));
+                (r % n as u64) as usize
+            },
+            Routing::ConsistentHash(key) => jump_hash(key(msg), n),
+        };
+        Some(workers[idx].clone())
+    }
+}
+
+/// Map the key to one of the buckets, using the jump consistent hash algorithm by Lamping and
+/// Veach. When the number of buckets changes, only the keys of the added or removed buckets move.
+fn jump_hash(mut key: u64, buckets: usize) -> usize {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as usize
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use crate::test_code::tests::{eventually, CounterCalls, CounterSends, FailingActor, FailingCalls, FailingReplies, FailingSends, SimpleCounter, StreamActor, StreamCalls};
+    use super::*;
+
+    /// Get the counts of the workers of the pool.
+    async fn counts(pool: &Pool<SimpleCounter>) -> Vec<u64> {
+        let mut counts = Vec::new();
+        for worker in pool.workers() {
+            counts.push(worker.call(CounterCalls::GetCount).await.unwrap().unwrap());
+        }
+        counts
+    }
+
+    /// Test that round robin routing uses the workers in turn.
+    #[tokio::test]
+    async fn test_round_robin() {
+        let pool = create_pool(3, Routing::RoundRobin, || SimpleCounter::new(false)).await.unwrap();
+        for _i in 0..7 {
+            pool.send(CounterSends::Count).await.unwrap();
+        }
+        assert_eq!(counts(&pool).await, vec![3, 2, 2]);
+        assert!(pool.call(CounterCalls::GetCount).await.unwrap().is_ok());
+    }
+
+    /// Test that messages are sent to the worker with the fewest waiting messages.
+    #[tokio::test]
+    async fn test_shortest_mailbox() {
+        let pool = create_pool(2, Routing::ShortestMailbox, || SimpleCounter::new(false)).await.unwrap();
+        let workers = pool.workers();
+        // the workers do not run until this task waits, so the messages stay in the mailboxes
+        for _i in 0..3 {
+            workers[0].send(CounterSends::Count).await.unwrap();
+        }
+        for _i in 0..3 {
+            pool.send(CounterSends::Count).await.unwrap();
+        }
+        assert_eq!(workers[0].mailbox_len(), 3);
+        assert_eq!(workers[1].mailbox_len(), 3);
+        assert_eq!(counts(&pool).await, vec![3, 3]);
+    }
+
+    /// Test that random routing delivers all messages.
+    #[tokio::test]
+    async fn test_random() {
+        let pool = create_pool(3, Routing::Random, || SimpleCounter::new(false)).await.unwrap();
+        for _i in 0..30 {
+            pool.send(CounterSends::Count).await.unwrap();
+        }
+        assert_eq!(counts(&pool).await.iter().sum::<u64>(), 30);
+    }
+
+    /// Test that messages with the same key are handled by the same worker.
+    #[tokio::test]
+    async fn test_consistent_hash() {
+        let routing = Routing::consistent_hash(|msg| match msg {
+            RoutedMessage::Send(value) => *value % 10,
+            RoutedMessage::Call(_) => 0,
+        });
+        let pool = create_pool(4, routing, StreamActor::new).await.unwrap();
+        for value in [3, 13, 23, 33] {
+            pool.send(value).await.unwrap();
+        }
+        let mut sums = Vec::new();
+        for worker in pool.workers() {
+            sums.push(worker.call(StreamCalls::Get).await.unwrap().unwrap().0);
+        }
+        sums.sort();
+        assert_eq!(sums, vec![0, 0, 0, 72]);
+    }
+
+    /// Test that only the keys of added buckets move when the number of buckets grows.
+    #[test]
+    fn test_jump_hash() {
+        for key in 0..1000u64 {
+            let key = key.wrapping_mul(0x9e3779b97f4a7c15);
+            let before = jump_hash(key, 4);
+            let after = jump_hash(key, 5);
+            assert!(before < 4);
+            assert!(after == before || after == 4);
+        }
+    }
+
+    /// Test that a worker that stops is replaced.
+    #[tokio::test]
+    async fn test_restart() {
+        let starts = Arc::new(AtomicU64::new(0));
+        let factory = {
+            let starts = starts.clone();
+            move || FailingActor::with_starts(starts.clone())
+        };
+        let pool = create_pool(2, Routing::RoundRobin, factory).await.unwrap();
+        let failed = pool.workers()[0].id();
+        pool.send(FailingSends::Fail).await.unwrap();
+        eventually(|| async { (pool.restarts() == 1).then_some(()) }).await;
+        assert_eq!(pool.size(), 2);
+        assert_ne!(pool.workers()[0].id(), failed);
+        assert_eq!(pool.call(FailingCalls::Ping).await.unwrap(), Ok(FailingReplies::Pong));
+        assert_eq!(starts.load(Ordering::Relaxed), 3);
+    }
+
+    /// Test that a worker that fails to initialize is removed, so that messages are only routed
+    /// to the other workers.
+    #[tokio::test]
+    async fn test_init_failed() {
+        let created = AtomicU64::new(0);
+        let factory = move || match created.fetch_add(1, Ordering::Relaxed) {
+            1 => FailingActor::failing_init(),
+            _ => FailingActor::new(),
+        };
+        let pool = create_pool(3, Routing::RoundRobin, factory).await.unwrap();
+        eventually(|| async { (pool.size() == 2).then_some(()) }).await;
+        for _i in 0..6 {
+            assert_eq!(pool.call(FailingCalls::Ping).await.unwrap(), Ok(FailingReplies::Pong));
+        }
+        assert_eq!(pool.restarts(), 0);
+    }
+
+    /// Test that a worker that panics during initialization is removed rather than restarted.
+    #[tokio::test]
+    async fn test_init_panicked() {
+        let created = AtomicU64::new(0);
+        let factory = move || match created.fetch_add(1, Ordering::Relaxed) {
+            1 => FailingActor::panicking_init(),
+            _ => FailingActor::new(),
+        };
+        let pool = create_pool(3, Routing::RoundRobin, factory).await.unwrap();
+        eventually(|| async { (pool.size() == 2).then_some(()) }).await;
+        for _i in 0..6 {
+            assert_eq!(pool.call(FailingCalls::Ping).await.unwrap(), Ok(FailingReplies::Pong));
+        }
+        assert_eq!(pool.restarts(), 0);
+    }
+
+    /// Test that the pool can grow and shrink.
+    #[tokio::test]
+    async fn test_resize() {
+        let pool = create_pool(2, Routing::RoundRobin, || SimpleCounter::new(false)).await.unwrap();
+        pool.resize(4).await.unwrap();
+        assert_eq!(pool.size(), 4);
+        let removed = pool.workers().split_off(1);
+        pool.resize(1).await.unwrap();
+        assert_eq!(pool.size(), 1);
+        // the removed workers shut down
+        for worker in removed {
+            eventually(|| async { worker.send(CounterSends::Count).await.err() }).await;
+        }
+        assert_eq!(pool.restarts(), 0);
+        assert_eq!(pool.resize(0).await, Err(Error::InvalidConfig));
+    }
+}
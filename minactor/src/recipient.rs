@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use crate::{Actor, ActorId, ActorRef, SendError};
+
+
+/// A type-erased handle for sending messages of type M to an actor, created using
+/// [ActorRef::recipient()] or [ActorRef::recipient_with()].
+///
+/// A Recipient only depends on the message type, not on the type of the actor. This makes it
+/// possible to store different types of actors that accept the same messages in one collection,
+/// or to replace an actor with a different one in tests.
+///
+/// Like an [ActorRef], a Recipient can be cloned and keeps the actor alive.
+pub struct Recipient<M> {
+    inner: Arc<dyn Deliver<M>>,
+}
+
+impl<M> Recipient<M>
+where M: Send + 'static
+{
+    /// Send a message to the actor.
+    ///
+    /// If the message could not be sent, then it is returned in the [SendError], unless the
+    /// recipient maps the message. In that case the mapped message can be taken from the
+    /// [Error](crate::Error) that the [SendError] converts into.
+    pub async fn send(&self, msg: M) -> Result<(), SendError<M>> {
+        self.inner.send(msg).await
+    }
+
+    /// Send a message to the actor without waiting for space in its mailbox, see
+    /// [ActorRef::try_send()].
+    pub fn try_send(&self, msg: M) -> Result<(), SendError<M>> {
+        self.inner.try_send(msg)
+    }
+
+    /// Get the id of the actor.
+    pub fn id(&self) -> ActorId {
+        self.inner.id()
+    }
+}
+
+impl<M> Clone for Recipient<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<A> ActorRef<A>
+where A: Actor + 'static
+{
+    /// Create a [Recipient] for the send messages of the actor.
+    pub fn recipient(&self) -> Recipient<A::SendMessage> {
+        Recipient { inner: Arc::new(self.clone()) }
+    }
+
+    /// Create a [Recipient] for messages of type M, which are converted into send messages of
+    /// the actor using the map function.
+    ///
+    /// Messages are mapped before they are sent, so a message that could not be sent is not
+    /// returned in the [SendError]. Instead, the mapped message can be taken from the
+    /// [Error](crate::Error) that the [SendError] converts into, see
+    /// [Error::into_message()](crate::Error::into_message).
+    pub fn recipient_with<M, F>(&self, map: F) -> Recipient<M>
+    where
+        M: Send + 'static,
+        F: Fn(M) -> A::SendMessage + Send + Sync + 'static,
+    {
+        Recipient { inner: Arc::new(Mapped { actor: self.clone(), map }) }
+    }
+}
+
+/// Delivers messages of type M to an actor.
+trait Deliver<M>: Send + Sync {
+    /// Send the message, see [Recipient::send()].
+    fn send(&self, msg: M) -> BoxFuture<'_, Result<(), SendError<M>>>;
+
+    /// Send the message without waiting, see [Recipient::try_send()].
+    fn try_send(&self, msg: M) -> Result<(), SendError<M>>;
+
+    /// The id of the actor.
+    fn id(&self) -> ActorId;
+}
+
+impl<A> Deliver<A::SendMessage> for ActorRef<A>
+where A: Actor + 'static
+{
+    fn send(&self, msg: A::SendMessage) -> BoxFuture<'_, Result<(), SendError<A::SendMessage>>> {
+        ActorRef::send(self, msg).boxed()
+    }
+
+    fn try_send(&self, msg: A::SendMessage) -> Result<(), SendError<A::SendMessage>> {
+        ActorRef::try_send(self, msg)
+    }
+
+    fn id(&self) -> ActorId {
+        ActorRef::id(self)
+    }
+}
+
+/// An actor with a function that converts messages into its send messages.
+struct Mapped<A, F>
+where A: Actor
+{
+    actor: ActorRef<A>,
+    map: F,
+}
+
+impl<A, M, F> Deliver<M> for Mapped<A, F>
+where
+    A: Actor + 'static,
+    M: Send + 'static,
+    F: Fn(M) -> A::SendMessage + Send + Sync,
+{
+    fn send(&self, msg: M) -> BoxFuture<'_, Result<(), SendError<M>>> {
+        let msg = (self.map)(msg);
+        async move {
+            self.actor.send(msg).await.map_err(unmapped)
+        }.boxed()
+    }
+
+    fn try_send(&self, msg: M) -> Result<(), SendError<M>> {
+        self.actor.try_send((self.map)(msg)).map_err(unmapped)
+    }
+
+    fn id(&self) -> ActorId {
+        self.actor.id()
+    }
+}
+
+/// Convert the error from sending a mapped message, keeping the mapped message in the [Error].
+fn unmapped<N, M>(e: SendError<N>) -> SendError<M>
+where N: Send + Sync + 'static
+{
+    SendError::new(e.into(), None)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_actor, Error};
+    use crate::test_code::tests::{CounterCalls, CounterSends, SimpleCounter, StreamActor, StreamCalls};
+    use super::*;
+
+    /// Test that actors of different types can be used through recipients.
+    #[tokio::test]
+    async fn test_recipients() {
+        let (stream, _stream_handle) = create_actor(StreamActor::new()).await.unwrap();
+        let (counter, _counter_handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let recipients: Vec<Recipient<u64>> = vec![
+            stream.recipient(),
+            counter.recipient_with(|_n: u64| CounterSends::Count),
+        ];
+        for recipient in recipients.iter() {
+            recipient.send(5).await.unwrap();
+            recipient.clone().try_send(6).unwrap();
+        }
+        assert_eq!(recipients[1].id(), counter.id());
+        assert_eq!(stream.call(StreamCalls::Get).await.unwrap().unwrap().0, 11);
+        assert_eq!(counter.call(CounterCalls::GetCount).await.unwrap().unwrap(), 2);
+    }
+
+    /// Test that the undelivered message is returned.
+    #[tokio::test]
+    async fn test_recipient_stopped() {
+        let (counter, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let recipient = counter.recipient();
+        let mapped = counter.recipient_with(|_n: u64| CounterSends::Count);
+        counter.shutdown().await.unwrap();
+        handle.await;
+        let e = recipient.send(CounterSends::Count).await.unwrap_err();
+        assert_eq!(e.into_message(), Some(CounterSends::Count));
+        let e = Error::from(mapped.send(1).await.unwrap_err());
+        assert!(matches!(e, Error::ActorStopped { .. }));
+        assert_eq!(e.into_message::<CounterSends>(), Some(CounterSends::Count));
+    }
+}
@@ -0,0 +1,171 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use crate::{Actor, ActorConfig, ActorHandle, ActorId, ActorRef, Error, WeakActorRef};
+use crate::actor::create_actor_with_config;
+use crate::monitor::MonitorGuard;
+use crate::result::Result;
+
+
+/// The process-wide registry of named actors.
+static REGISTRY: LazyLock<Mutex<HashMap<String, Entry>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// An entry in the registry.
+struct Entry {
+    /// The id of the registered actor.
+    id: ActorId,
+    /// The [WeakActorRef] of the registered actor.
+    actor_ref: Box<dyn Any + Send + Sync>,
+    /// The monitor that removes the entry when the actor stops, None until it has been added.
+    monitor: Option<MonitorGuard>,
+}
+
+/// Create an instance of an actor using default configuration and register it under the name.
+///
+/// Returns [Error::NameAlreadyRegistered] if another actor is registered under the name, in which
+/// case the actor is not created.
+pub async fn create_named_actor<T>(name: impl Into<String>, instance: T) -> Result<(ActorRef<T>, ActorHandle<T>)>
+where
+    T: Actor + Send + Sync + 'static
+{
+    create_named_actor_with_config(name, instance, ActorConfig::new()).await
+}
+
+/// Create an instance of an actor using the given configuration and register it under the name.
+///
+/// Returns [Error::NameAlreadyRegistered] if another actor is registered under the name, in which
+/// case the actor is not created, and [Error::InvalidConfig] if the configuration is not valid.
+pub async fn create_named_actor_with_config<T>(name: impl Into<String>, instance: T, config: ActorConfig) -> Result<(ActorRef<T>, ActorHandle<T>)>
+where
+    T: Actor + Send + Sync + 'static
+{
+    let name = name.into();
+    if REGISTRY.lock().unwrap().contains_key(&name) {
+        return Err(Error::NameAlreadyRegistered { name });
+    }
+    let (actor_ref, handle) = create_actor_with_config(instance, config).await?;
+    if let Err(e) = register(name, &actor_ref) {
+        // another actor took the name in the meantime
+        actor_ref.terminate();
+        return Err(e);
+    }
+    Ok((actor_ref, handle))
+}
+
+/// Register an actor under a name.
+///
+/// The registration is removed automatically when the actor stops. The registry does not keep the
+/// actor alive. Returns [Error::NameAlreadyRegistered] if another actor is registered under the name.
+pub fn register<A>(name: impl Into<String>, actor_ref: &ActorRef<A>) -> Result<()>
+where
+    A: Actor + Send + Sync + 'static
+{
+    let name = name.into();
+    {
+        let mut registry = REGISTRY.lock().unwrap();
+        if registry.contains_key(&name) {
+            return Err(Error::NameAlreadyRegistered { name });
+        }
+        registry.insert(name.clone(), Entry { id: actor_ref.id(), actor_ref: Box::new(actor_ref.downgrade()), monitor: None });
+    }
+    // remove the registration when the actor stops
+    let remove_name = name.clone();
+    MonitorGuard::add(&actor_ref.watchers, actor_ref.id(), Box::new(move |down| remove(&remove_name, down.id)), |guard| {
+        let mut registry = REGISTRY.lock().unwrap();
+        match registry.get_mut(&name) {
+            Some(entry) if entry.id == actor_ref.id() => {
+                entry.monitor = Some(guard);
+                None
+            },
+            // the registration was removed in the meantime
+            _ => Some(guard),
+        }
+    });
+    Ok(())
+}
+
+/// Remove the registration of the name. This has no effect on the actor itself.
+pub fn unregister(name: &str) {
+    let entry = REGISTRY.lock().unwrap().remove(name);
+    if let Some(monitor) = entry.and_then(|e| e.monitor) {
+        monitor.remove();
+    }
+}
+
+/// Look up the actor that is registered under the name.
+///
+/// Returns None if there is no actor registered under the name, if the registered actor is not
+/// an instance of actor A, or if the registered actor is stopping.
+pub fn lookup<A>(name: &str) -> Option<ActorRef<A>>
+where
+    A: Actor + Send + Sync + 'static
+{
+    REGISTRY.lock().unwrap()
+        .get(name)
+        .and_then(|e| e.actor_ref.downcast_ref::<WeakActorRef<A>>())
+        .and_then(|r| r.upgrade())
+}
+
+/// Remove the registration of the name if it still belongs to the actor.
+fn remove(name: &str, id: ActorId) {
+    let mut registry = REGISTRY.lock().unwrap();
+    if registry.get(name).is_some_and(|e| e.id == id) {
+        registry.remove(name);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::create_actor;
+    use crate::test_code::tests::{FailingActor, FailingCalls, FailingReplies, SimpleCounter};
+    use super::*;
+
+    /// Test that registered actors can be looked up and are removed when they stop.
+    #[tokio::test]
+    async fn test_register_lookup() {
+        let (actor, handle) = create_named_actor("test_register_lookup", FailingActor::new()).await.unwrap();
+        let found = lookup::<FailingActor>("test_register_lookup").unwrap();
+        assert_eq!(found.id(), actor.id());
+        assert_eq!(found.call(FailingCalls::Ping).await.unwrap(), Ok(FailingReplies::Pong));
+        // the wrong actor type is not found
+        assert!(lookup::<SimpleCounter>("test_register_lookup").is_none());
+        actor.shutdown().await.unwrap();
+        handle.await;
+        assert!(lookup::<FailingActor>("test_register_lookup").is_none());
+    }
+
+    /// Test that unregistering removes the monitor from the actor.
+    #[tokio::test]
+    async fn test_unregister_monitor() {
+        let (actor, _handle) = create_actor(FailingActor::new()).await.unwrap();
+        for _i in 0..10 {
+            register("test_unregister_monitor", &actor).unwrap();
+            unregister("test_unregister_monitor");
+        }
+        assert_eq!(actor.watchers.monitors(), 0);
+    }
+
+    /// Test that a named actor can be created with a configuration.
+    #[tokio::test]
+    async fn test_named_with_config() {
+        let config = ActorConfig::new().mailbox_size(1);
+        let (actor, _handle) = create_named_actor_with_config("test_named_with_config", FailingActor::new(), config).await.unwrap();
+        assert_eq!(lookup::<FailingActor>("test_named_with_config").map(|a| a.id()), Some(actor.id()));
+        let config = ActorConfig::new().mailbox_size(0);
+        let r = create_named_actor_with_config("test_named_with_config_invalid", FailingActor::new(), config).await;
+        assert!(matches!(r, Err(Error::InvalidConfig)));
+        assert!(lookup::<FailingActor>("test_named_with_config_invalid").is_none());
+    }
+
+    /// Test that a name can only be registered once.
+    #[tokio::test]
+    async fn test_name_taken() {
+        let (_actor, _handle) = create_named_actor("test_name_taken", FailingActor::new()).await.unwrap();
+        let r = create_named_actor("test_name_taken", FailingActor::new()).await;
+        assert!(matches!(r, Err(Error::NameAlreadyRegistered { .. })));
+        unregister("test_name_taken");
+        let r = create_named_actor("test_name_taken", FailingActor::new()).await;
+        assert!(r.is_ok());
+    }
+}
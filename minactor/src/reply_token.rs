@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::warn;
+use tokio::sync::oneshot;
+use crate::{Actor, Error};
+use crate::executor::ReplyResult;
+use crate::result::ActorInfo;
+
+
+/// A token that is used to reply to a call message at a later time.
+///
+/// The token is passed to [Actor::handle_calls_deferred()]. It can be stored in the actor and used
+/// when handling a later message, or it can be moved into a future that is spawned using
+/// [Control::SpawnFuture](crate::Control::SpawnFuture). The actor continues to handle other
+/// messages in the meantime.
+///
+/// If the token is dropped without replying, the caller receives [Error::ReplyDropped], or
+/// [Error::ActorPanicked] if the actor panicked. The same happens when the actor stops while the
+/// token is still stored in it. A reply sent using the token after that is discarded.
+pub struct ReplyToken<A>
+where A: Actor + ?Sized
+{
+    /// Identifies the call in the pending replies.
+    key: u64,
+    /// The calls of the actor that are waiting for a reply.
+    pending: Arc<PendingReplies<A>>,
+    /// The actor that is replying.
+    actor: ActorInfo,
+}
+
+impl<A> ReplyToken<A>
+where A: Actor + ?Sized
+{
+    pub(crate) fn new(dest: oneshot::Sender<ReplyResult<A>>, actor: ActorInfo, pending: Arc<PendingReplies<A>>) -> Self {
+        let key = pending.add(dest);
+        Self { key, pending, actor }
+    }
+
+    /// Send the reply to the caller.
+    pub fn reply(self, result: Result<A::CallReply, A::ErrorType>) {
+        if let Some(dest) = self.pending.take(self.key) {
+            if dest.send(Ok(result)).is_err() {
+                warn!("unable to send reply of call message to caller.");
+            }
+        }
+    }
+
+    /// Returns true if the caller is no longer waiting for the reply, for example because the
+    /// call timed out.
+    pub fn is_closed(&self) -> bool {
+        self.pending.dests.lock().unwrap().dests.get(&self.key).is_none_or(|d| d.is_closed())
+    }
+}
+
+impl<A> Drop for ReplyToken<A>
+where A: Actor + ?Sized
+{
+    fn drop(&mut self) {
+        if let Some(dest) = self.pending.take(self.key) {
+            let _ = dest.send(Err(self.pending.error(self.actor.clone())));
+        }
+    }
+}
+
+/// The calls of an actor that are waiting for a reply through a [ReplyToken], shared by the
+/// tokens and the executor of the actor so that the callers can be answered when the actor stops.
+pub(crate) struct PendingReplies<A>
+where A: Actor + ?Sized
+{
+    /// Where to send the replies.
+    dests: Mutex<Dests<A>>,
+    /// Set when the actor has panicked.
+    panicked: AtomicBool,
+}
+
+/// Where to send the replies, by the key of their token.
+struct Dests<A>
+where A: Actor + ?Sized
+{
+    dests: HashMap<u64, oneshot::Sender<ReplyResult<A>>>,
+    next_key: u64,
+}
+
+impl<A> PendingReplies<A>
+where A: Actor + ?Sized
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            dests: Mutex::new(Dests { dests: HashMap::new(), next_key: 0 }),
+            panicked: AtomicBool::new(false),
+        }
+    }
+
+    /// Record that the actor has panicked, callers that have not received a reply get
+    /// [Error::ActorPanicked].
+    pub(crate) fn set_panicked(&self) {
+        self.panicked.store(true, Ordering::Relaxed);
+    }
+
+    /// The actor has stopped, answer the callers that are still waiting with an error.
+    pub(crate) fn stopped(&self, actor: &ActorInfo) {
+        let dests: Vec<_> = self.dests.lock().unwrap().dests.drain().map(|(_, d)| d).collect();
+        for dest in dests {
+            let _ = dest.send(Err(self.error(actor.clone())));
+        }
+    }
+
+    /// Add the destination of a reply and return its key.
+    fn add(&self, dest: oneshot::Sender<ReplyResult<A>>) -> u64 {
+        let mut dests = self.dests.lock().unwrap();
+        let key = dests.next_key;
+        dests.next_key += 1;
+        dests.dests.insert(key, dest);
+        key
+    }
+
+    /// Take the destination of a reply, None if the reply has already been sent or the actor
+    /// has stopped.
+    fn take(&self, key: u64) -> Option<oneshot::Sender<ReplyResult<A>>> {
+        self.dests.lock().unwrap().dests.remove(&key)
+    }
+
+    /// The error for a caller that will not receive a reply.
+    fn error(&self, actor: ActorInfo) -> Error {
+        // the token is dropped while unwinding if the handler that holds it panics
+        if self.panicked.load(Ordering::Relaxed) || std::thread::panicking() {
+            Error::ActorPanicked { actor }
+        } else {
+            Error::ReplyDropped { actor }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_actor, Error, ExitReason};
+    use crate::test_code::tests::{eventually, DeferringActor, DeferringCalls};
+
+    /// Test that replies can be sent when handling a later message.
+    #[tokio::test]
+    async fn test_deferred_reply() {
+        let (actor, _handle) = create_actor(DeferringActor::new()).await.unwrap();
+        let first = tokio::spawn({
+            let actor = actor.clone();
+            async move { actor.call(DeferringCalls::Wait).await }
+        });
+        let second = tokio::spawn({
+            let actor = actor.clone();
+            async move { actor.call(DeferringCalls::Wait).await }
+        });
+        // the actor is not blocked by the waiting calls
+        eventually(|| async { (actor.call(DeferringCalls::Waiting).await.unwrap() == Ok(2)).then_some(()) }).await;
+        actor.send(7).await.unwrap();
+        assert_eq!(first.await.unwrap().unwrap(), Ok(7));
+        assert_eq!(second.await.unwrap().unwrap(), Ok(7));
+    }
+
+    /// Test that replies can be sent from a spawned future.
+    #[tokio::test]
+    async fn test_reply_from_future() {
+        let (actor, _handle) = create_actor(DeferringActor::new()).await.unwrap();
+        assert_eq!(actor.call(DeferringCalls::Later(3)).await.unwrap(), Ok(3));
+    }
+
+    /// Test that the caller gets an error if the token is dropped.
+    #[tokio::test]
+    async fn test_reply_dropped() {
+        let (actor, _handle) = create_actor(DeferringActor::new()).await.unwrap();
+        let e = actor.call(DeferringCalls::Forget).await.unwrap_err();
+        assert_eq!(e.error(), &Error::ReplyDropped { actor: actor.info() });
+    }
+
+    /// Test that the caller gets an error if the actor stops with the token stored in it, even
+    /// though the final state of the actor is kept by its handle.
+    #[tokio::test]
+    async fn test_reply_stopped() {
+        let (actor, handle) = create_actor(DeferringActor::new()).await.unwrap();
+        let waiting = tokio::spawn({
+            let actor = actor.clone();
+            async move { actor.call(DeferringCalls::Wait).await }
+        });
+        eventually(|| async { (actor.call(DeferringCalls::Waiting).await.unwrap() == Ok(1)).then_some(()) }).await;
+        actor.shutdown().await.unwrap();
+        let (reason, state) = handle.join().await;
+        assert_eq!(reason, ExitReason::Shutdown);
+        let e = waiting.await.unwrap().unwrap_err();
+        assert_eq!(e.error(), &Error::ReplyDropped { actor: actor.info() });
+        drop(state);
+    }
+}
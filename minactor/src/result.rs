@@ -1,4 +1,7 @@
-
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+use crate::ActorId;
 
 /// Standard Result used in the library
 #[doc(hidden)]
@@ -6,18 +9,297 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 /// Standard error type used in the library
 #[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     /// An unrecognized message was received.
     UnrecognizedMessage,
     /// The handler was not implemented, for example a message was sent with no handler defined.
     HandlerNotImplemented,
-    /// Unable to send a message, probably due to actor termination.
-    UnableToSend,
-    /// Unable to receive a message, probably due to actor termination.
-    UnableToReceive,
+    /// Unable to receive the reply to a call, probably due to actor termination.
+    UnableToReceive {
+        /// The actor that was called.
+        actor: ActorInfo,
+    },
     /// Processing has been interrupted due to a terminate instruction.
     Terminated,
+    /// The actor panicked before it could reply to the call.
+    ActorPanicked {
+        /// The actor that panicked.
+        actor: ActorInfo,
+    },
+    /// Another actor is already registered under the name.
+    NameAlreadyRegistered {
+        /// The name.
+        name: String,
+    },
+    /// The actor configuration is not valid.
+    InvalidConfig,
+    /// The mailbox of the actor is full.
+    MailboxFull {
+        /// The actor that the message was sent to.
+        actor: ActorInfo,
+        /// The message that was not delivered.
+        message: Option<Undelivered>,
+    },
+    /// The operation did not complete in time.
+    Timeout {
+        /// The actor that the message was sent to.
+        actor: ActorInfo,
+        /// What was being waited for when the time ran out.
+        stage: Stage,
+    },
+    /// The actor has stopped and no longer accepts messages.
+    ActorStopped {
+        /// The actor that the message was sent to.
+        actor: ActorInfo,
+        /// The message that was not delivered.
+        message: Option<Undelivered>,
+    },
+    /// The actor dropped the [ReplyToken](crate::ReplyToken) of the call without replying.
+    ReplyDropped {
+        /// The actor that was called.
+        actor: ActorInfo,
+    },
+    /// The [Pool](crate::Pool) has no workers, because they all failed to initialize.
+    NoWorkers,
+}
+
+impl Error {
+    /// The actor that the error relates to, if any.
+    pub fn actor(&self) -> Option<&ActorInfo> {
+        match self {
+            Error::UnableToReceive { actor } | Error::ActorPanicked { actor } |
+            Error::MailboxFull { actor, .. } | Error::Timeout { actor, .. } |
+            Error::ActorStopped { actor, .. } | Error::ReplyDropped { actor } => Some(actor),
+            _ => None,
+        }
+    }
+
+    /// Where in the delivery of a message the error happened, if the error relates to a message.
+    pub fn stage(&self) -> Option<Stage> {
+        match self {
+            Error::MailboxFull { .. } | Error::ActorStopped { .. } => Some(Stage::Enqueue),
+            Error::UnableToReceive { .. } | Error::ActorPanicked { .. } |
+            Error::ReplyDropped { .. } => Some(Stage::AwaitingReply),
+            Error::Timeout { stage, .. } => Some(*stage),
+            _ => None,
+        }
+    }
+
+    /// Take the message that was not delivered, so that it can be sent again.
+    ///
+    /// Returns None if there is no undelivered message or if it is not of type M.
+    pub fn into_message<M: 'static>(self) -> Option<M> {
+        match self {
+            Error::MailboxFull { message, .. } | Error::ActorStopped { message, .. } => message.and_then(|m| m.downcast()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnrecognizedMessage => write!(f, "unrecognized message"),
+            Error::HandlerNotImplemented => write!(f, "handler not implemented"),
+            Error::UnableToReceive { actor } => write!(f, "unable to receive reply from {}", actor),
+            Error::Terminated => write!(f, "terminated"),
+            Error::ActorPanicked { actor } => write!(f, "{} panicked", actor),
+            Error::NameAlreadyRegistered { name } => write!(f, "name {} is already registered", name),
+            Error::InvalidConfig => write!(f, "invalid actor configuration"),
+            Error::MailboxFull { actor, .. } => write!(f, "mailbox of {} is full", actor),
+            Error::Timeout { actor, stage } => write!(f, "timed out {} {}", stage, actor),
+            Error::ActorStopped { actor, .. } => write!(f, "{} has stopped", actor),
+            Error::ReplyDropped { actor } => write!(f, "{} dropped the call without replying", actor),
+            Error::NoWorkers => write!(f, "pool has no workers"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Identifies the actor that an [Error] relates to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActorInfo {
+    id: ActorId,
+    name: Option<Arc<str>>,
+}
+
+impl ActorInfo {
+    pub(crate) fn new(id: ActorId, name: Option<Arc<str>>) -> Self {
+        Self { id, name }
+    }
+
+    /// The id of the actor.
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// The name of the actor, if it was given one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl fmt::Display for ActorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({})", self.id, name),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
+
+/// Where in the delivery of a message an [Error] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// While putting the message in the mailbox of the actor. The actor did not receive the message.
+    Enqueue,
+    /// While waiting for the reply to a call. The actor may have handled the message.
+    AwaitingReply,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Enqueue => write!(f, "sending to"),
+            Stage::AwaitingReply => write!(f, "waiting for reply from"),
+        }
+    }
+}
+
+/// The error returned when a message could not be sent to an actor or a call was not answered.
+///
+/// If the message was not delivered, then it is returned so that it can be sent again, for
+/// example to a replacement actor or a dead letter sink. A SendError converts into an [Error],
+/// so the `?` operator can be used in functions that return [Error].
+pub struct SendError<M> {
+    /// What went wrong.
+    error: Error,
+    /// The message, if it was not delivered.
+    message: Option<M>,
+}
+
+impl<M> SendError<M> {
+    pub(crate) fn new(error: Error, message: Option<M>) -> Self {
+        Self { error, message }
+    }
+
+    /// What went wrong.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// The message, if it was not delivered.
+    pub fn message(&self) -> Option<&M> {
+        self.message.as_ref()
+    }
+
+    /// Take the message, if it was not delivered.
+    pub fn into_message(self) -> Option<M> {
+        self.message
+    }
+
+    /// Split the error into what went wrong and the message, if it was not delivered.
+    pub fn into_parts(self) -> (Error, Option<M>) {
+        (self.error, self.message)
+    }
+}
+
+impl<M> From<SendError<M>> for Error
+where M: Send + Sync + 'static {
+    fn from(e: SendError<M>) -> Self {
+        let undelivered = e.message.map(Undelivered::new);
+        match e.error {
+            Error::MailboxFull { actor, message } => Error::MailboxFull { actor, message: undelivered.or(message) },
+            Error::ActorStopped { actor, message } => Error::ActorStopped { actor, message: undelivered.or(message) },
+            error => error,
+        }
+    }
+}
+
+impl<M> fmt::Debug for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError")
+            .field("error", &self.error)
+            .field("undelivered", &self.message.is_some())
+            .finish()
+    }
 }
 
-// toco: implement display
+impl<M> fmt::Display for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<M> std::error::Error for SendError<M> {}
+
+impl<M: PartialEq> PartialEq for SendError<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.error == other.error && self.message == other.message
+    }
+}
+
+impl<M: Eq> Eq for SendError<M> {}
+
+/// A message that could not be delivered, see [Error::into_message()].
+///
+/// Undelivered messages are not compared when comparing errors.
+pub struct Undelivered(Box<dyn Any + Send + Sync>);
 
+impl Undelivered {
+    pub(crate) fn new<M: Send + Sync + 'static>(msg: M) -> Self {
+        Self(Box::new(msg))
+    }
+
+    /// Get the message, if it is of type M.
+    pub fn downcast<M: 'static>(self) -> Option<M> {
+        self.0.downcast().ok().map(|m| *m)
+    }
+}
+
+impl fmt::Debug for Undelivered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Undelivered")
+    }
+}
+
+impl PartialEq for Undelivered {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Undelivered {}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::create_actor;
+    use crate::test_code::tests::{CounterSends, SimpleCounter};
+    use super::*;
+
+    /// Test that the context and the undelivered message are available from the error.
+    #[tokio::test]
+    async fn test_error_context() {
+        let (actor, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        actor.shutdown().await.unwrap();
+        handle.await;
+        let e = actor.send(CounterSends::Count).await.unwrap_err();
+        assert_eq!(e.message(), Some(&CounterSends::Count));
+        // the message is kept when converting to Error
+        let e = Error::from(e);
+        assert_eq!(e.actor().map(|a| a.id()), Some(actor.id()));
+        assert_eq!(e.stage(), Some(Stage::Enqueue));
+        assert_eq!(e.to_string(), format!("{} has stopped", actor.id()));
+        assert_eq!(e.into_message::<CounterSends>(), Some(CounterSends::Count));
+    }
+
+    /// Test that the error can be used as a standard error.
+    #[test]
+    fn test_std_error() {
+        let e: Box<dyn std::error::Error + Send + Sync> = Box::new(Error::NameAlreadyRegistered { name: "a".to_string() });
+        assert_eq!(e.to_string(), "name a is already registered");
+    }
+}
@@ -0,0 +1,89 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use futures::{Stream, StreamExt};
+use crate::{Actor, ActorRef};
+use crate::executor::ActorSysMsg;
+use crate::result::Result;
+
+
+/// The next stream id to be allocated.
+static NEXT_STREAM_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifies a stream that has been attached to an actor using [ActorRef::attach_stream()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamId(u64);
+
+/// An event produced by an attached stream.
+pub(crate) enum StreamEvent<M> {
+    /// An item from the stream.
+    Item(M),
+    /// The stream has finished.
+    Finished(StreamId),
+}
+
+/// An attached stream, as it is held by the executor.
+pub(crate) type AttachedStream<M> = Pin<Box<dyn Stream<Item = StreamEvent<M>> + Send>>;
+
+impl<A> ActorRef<A>
+where
+    A: Actor + Send + Sync + 'static
+{
+    /// Attach a stream to the actor as a source of messages.
+    ///
+    /// Each item of the stream is handled by [Actor::handle_sends()], interleaved with the other
+    /// messages of the actor. When the stream finishes, [Actor::on_stream_finished()] is called with
+    /// the returned id. The stream is dropped when the actor stops.
+    pub async fn attach_stream<S>(&self, stream: S) -> Result<StreamId>
+    where
+        S: Stream<Item = A::SendMessage> + Send + 'static
+    {
+        let id = StreamId(NEXT_STREAM_ID.fetch_add(1, Ordering::Relaxed));
+        let events = stream
+            .map(StreamEvent::Item)
+            .chain(futures::stream::once(async move { StreamEvent::Finished(id) }));
+        self.send_sys(ActorSysMsg::AttachStream(Box::pin(events))).await?;
+        Ok(id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::create_actor;
+    use crate::test_code::tests::{eventually, StreamActor, StreamCalls};
+    use super::*;
+
+    /// Get the totals of the stream actor, once they satisfy the condition.
+    async fn totals_when(actor: &ActorRef<StreamActor>, f: impl Fn(u64, &[StreamId]) -> bool) -> (u64, Vec<StreamId>) {
+        eventually(|| async {
+            let (sum, ids) = actor.call(StreamCalls::Get).await.unwrap().unwrap();
+            f(sum, &ids).then_some((sum, ids))
+        }).await
+    }
+
+    /// Test that items of attached streams are handled and their completion is reported.
+    #[tokio::test]
+    async fn test_attach_stream() {
+        let (actor, _handle) = create_actor(StreamActor::new()).await.unwrap();
+        let a = actor.attach_stream(futures::stream::iter(vec![1, 2, 3])).await.unwrap();
+        let b = actor.attach_stream(futures::stream::iter(vec![10, 20])).await.unwrap();
+        actor.send(100).await.unwrap();
+        let (sum, mut ids) = totals_when(&actor, |_, ids| ids.len() == 2).await;
+        assert_eq!(sum, 136);
+        ids.sort_by_key(|id| id.0);
+        assert_eq!(ids, vec![a, b]);
+    }
+
+    /// Test that attached streams are dropped when the actor stops.
+    #[tokio::test]
+    async fn test_stream_dropped_on_stop() {
+        let (actor, handle) = create_actor(StreamActor::new()).await.unwrap();
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        actor.attach_stream(receiver).await.unwrap();
+        sender.unbounded_send(5).unwrap();
+        totals_when(&actor, |sum, _| sum == 5).await;
+        actor.shutdown().await.unwrap();
+        handle.await;
+        assert!(sender.is_closed());
+    }
+}
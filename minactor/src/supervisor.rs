@@ -0,0 +1,589 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use log::warn;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use crate::{Actor, ActorConfig, ActorRef, Control, Error, ExitReason, WeakActorRef};
+#[cfg(feature = "metrics")]
+use crate::MetricsRecorder;
+use crate::actor::spawn_actor;
+use crate::result::Result;
+
+
+/// The default maximum number of restarts that are allowed within the restart window.
+const DEFAULT_MAX_RESTARTS: usize = 1;
+/// The default restart window.
+const DEFAULT_RESTART_WINDOW: Duration = Duration::from_secs(5);
+/// The default time that a child is given to shut down before it is terminated.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The strategy that a [Supervisor] uses when one of its children fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupervisorStrategy {
+    /// Only the failed child is restarted.
+    OneForOne,
+    /// All children are stopped and then restarted.
+    OneForAll,
+    /// The failed child and all children that were started after it are stopped and restarted.
+    RestForOne,
+}
+
+/// Determines when a child of a [Supervisor] is restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// The child is always restarted.
+    Permanent,
+    /// The child is only restarted if it stops abnormally, see [ExitReason::is_normal()].
+    Transient,
+    /// The child is never restarted.
+    Temporary,
+}
+
+/// Function that starts a child, the token is the termination token of the supervisor.
+type StartFn = Box<dyn Fn(&ActorConfig, CancellationToken) -> Result<StartedChild> + Send + Sync>;
+
+/// The specification of a child of a [Supervisor].
+///
+/// The specification contains a factory function that creates a new instance of the actor each
+/// time the child is started.
+pub struct ChildSpec {
+    /// The identifier of the child, unique within the supervisor.
+    id: String,
+    /// When the child should be restarted.
+    restart: Restart,
+    /// How long the child is given to shut down before it is terminated.
+    shutdown_timeout: Duration,
+    /// The configuration used to create the child.
+    config: ActorConfig,
+    /// Starts the child.
+    start: StartFn,
+    /// The type of the child, which labels its metrics if it has no name.
+    #[cfg(feature = "metrics")]
+    type_name: &'static str,
+}
+
+impl ChildSpec {
+    /// Create a new child specification.
+    ///
+    /// The child is [Restart::Permanent] and has a shutdown timeout of 5 seconds, these can be
+    /// changed using [ChildSpec::restart()] and [ChildSpec::shutdown_timeout()].
+    pub fn new<A, F>(id: impl Into<String>, factory: F) -> Self
+    where
+        A: Actor + Send + Sync + 'static,
+        F: Fn() -> A + Send + Sync + 'static,
+    {
+        let start = move |config: &ActorConfig, parent_token| {
+            let config = config.clone().parent_token(parent_token);
+            let (actor_ref, handle) = spawn_actor(factory(), config)?;
+            Ok(StartedChild { actor_ref: Box::new(actor_ref), handle: Box::pin(handle) })
+        };
+        Self {
+            id: id.into(),
+            restart: Restart::Permanent,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            config: ActorConfig::new(),
+            start: Box::new(start),
+            #[cfg(feature = "metrics")]
+            type_name: std::any::type_name::<A>(),
+        }
+    }
+
+    /// Set the configuration that is used to create the child.
+    pub fn config(mut self, config: ActorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set when the child should be restarted.
+    pub fn restart(mut self, restart: Restart) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// Set how long the child is given to shut down before it is terminated.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+}
+
+/// A supervisor is an actor that starts, monitors and restarts other actors.
+///
+/// The supervisor starts its children, in order, when it is initialized. When a child stops, the
+/// supervisor uses the [Restart] setting of the child to decide whether it needs to be restarted
+/// and the [SupervisorStrategy] to decide which other children need to be restarted with it.
+///
+/// If more than `max_restarts` restarts occur within the restart window then the supervisor stops
+/// all of its children and terminates itself. If the supervisor is itself the child of another
+/// supervisor, this termination escalates the failure to the parent.
+///
+/// When the supervisor is shut down, its children are shut down in reverse order. When the
+/// supervisor is terminated, its children are also terminated.
+///
+/// ```no_run
+/// # use minactor::{create_actor, Actor, ChildSpec, Supervisor, SupervisorStrategy};
+/// # struct Worker;
+/// # impl Actor for Worker { type SendMessage = (); type CallMessage = (); type CallReply = (); type ErrorType = (); type FutureResult = (); }
+/// # async fn example() {
+/// let supervisor = Supervisor::new(SupervisorStrategy::OneForOne)
+///     .child(ChildSpec::new("worker", || Worker));
+/// let (sup_ref, _handle) = create_actor(supervisor).await.unwrap();
+/// let worker = sup_ref.child::<Worker>("worker").await.unwrap();
+/// # }
+/// ```
+pub struct Supervisor {
+    /// How failures are handled.
+    strategy: SupervisorStrategy,
+    /// Maximum number of restarts allowed within the restart window.
+    max_restarts: usize,
+    /// The restart window.
+    restart_window: Duration,
+    /// The children, in the order in which they are started.
+    children: Vec<Child>,
+    /// Times of recent restarts.
+    restarts: VecDeque<Instant>,
+    /// Reference to the supervisor itself, set during initialization.
+    self_ref: Option<WeakActorRef<Supervisor>>,
+    /// The recorder for restarts, which was in use when the supervisor was created.
+    #[cfg(feature = "metrics")]
+    recorder: Arc<dyn MetricsRecorder>,
+}
+
+impl Supervisor {
+    /// Create a new supervisor with no children.
+    ///
+    /// By default, a maximum of 1 restart is allowed within 5 seconds.
+    pub fn new(strategy: SupervisorStrategy) -> Self {
+        Self {
+            strategy,
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            restart_window: DEFAULT_RESTART_WINDOW,
+            children: Vec::new(),
+            restarts: VecDeque::new(),
+            self_ref: None,
+            #[cfg(feature = "metrics")]
+            recorder: crate::metrics::recorder(),
+        }
+    }
+
+    /// Set the maximum number of restarts that are allowed within the given window.
+    pub fn max_restarts(mut self, max_restarts: usize, within: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.restart_window = within;
+        self
+    }
+
+    /// Add a child to the supervisor. Children are started in the order in which they are added.
+    pub fn child(mut self, spec: ChildSpec) -> Self {
+        self.children.push(Child { spec, generation: 0, running: None });
+        self
+    }
+
+    /// Start the child at the given index.
+    fn start_child(&mut self, idx: usize) -> Result<()> {
+        let self_ref = self.self_ref.as_ref().expect("supervisor not initialized");
+        let child = &mut self.children[idx];
+        let started = (child.spec.start)(&child.spec.config, self_ref.terminate_token.clone())?;
+        child.generation += 1;
+        let stopped = CancellationToken::new();
+        child.running = Some(RunningChild { actor_ref: started.actor_ref, stopped: stopped.clone() });
+        // watch the child and notify the supervisor when it stops
+        let exit = ChildExit { id: child.spec.id.clone(), generation: child.generation, reason: ExitReason::Terminated };
+        let sup_ref = self_ref.clone();
+        let handle = started.handle;
+        tokio::spawn(async move {
+            let reason = handle.await;
+            stopped.cancel();
+            // the supervisor may already have stopped, in which case this is not needed
+            if let Some(sup_ref) = sup_ref.upgrade() {
+                let _ = sup_ref.send(SupervisorSends(ChildExit { reason, ..exit })).await;
+            }
+        });
+        Ok(())
+    }
+
+    /// Stop the child at the given index, if it is running.
+    ///
+    /// The child is first shut down and if it has not stopped within its shutdown timeout, then
+    /// it is terminated.
+    async fn stop_child(&mut self, idx: usize) {
+        let timeout = self.children[idx].spec.shutdown_timeout;
+        if let Some(running) = self.children[idx].running.take() {
+            let shutdown = async {
+                // an error means the child has already stopped
+                let _ = running.actor_ref.shutdown().await;
+                running.stopped.cancelled().await;
+            };
+            if tokio::time::timeout(timeout, shutdown).await.is_err() {
+                warn!("child {} did not shut down in time, terminating it.", self.children[idx].spec.id);
+                running.actor_ref.terminate();
+                running.stopped.cancelled().await;
+            }
+        }
+    }
+
+    /// Stop all the children from the given index onwards, in reverse order.
+    async fn stop_children_from(&mut self, first: usize) {
+        for idx in (first..self.children.len()).rev() {
+            self.stop_child(idx).await;
+        }
+    }
+
+    /// Record a restart and check whether the restart intensity has been exceeded.
+    fn restart_intensity_exceeded(&mut self) -> bool {
+        let now = Instant::now();
+        self.restarts.push_back(now);
+        while let Some(t) = self.restarts.front() {
+            if now.duration_since(*t) > self.restart_window {
+                self.restarts.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.restarts.len() > self.max_restarts
+    }
+
+    /// Handle the exit of a child.
+    async fn handle_exit(&mut self, exit: ChildExit) -> Control {
+        // exits of children that the supervisor stopped itself are ignored
+        let Some(idx) = self.children.iter().position(|c| {
+            c.spec.id == exit.id && c.generation == exit.generation && c.running.is_some()
+        }) else {
+            return Control::Ok;
+        };
+        self.children[idx].running = None;
+        let restart = match self.children[idx].spec.restart {
+            Restart::Permanent => true,
+            Restart::Transient => !exit.reason.is_normal(),
+            Restart::Temporary => false,
+        };
+        if !restart {
+            return Control::Ok;
+        }
+        if self.restart_intensity_exceeded() {
+            warn!("supervisor restart intensity exceeded, terminating.");
+            self.stop_children_from(0).await;
+            return Control::Terminate;
+        }
+        let (first, last) = match self.strategy {
+            SupervisorStrategy::OneForOne => (idx, idx + 1),
+            SupervisorStrategy::OneForAll => (0, self.children.len()),
+            SupervisorStrategy::RestForOne => (idx, self.children.len()),
+        };
+        // temporary children, and children that were not running, are not restarted
+        let to_restart: Vec<usize> = (first..last)
+            .filter(|i| *i == idx || (self.children[*i].running.is_some() && self.children[*i].spec.restart != Restart::Temporary))
+            .collect();
+        for i in (first..last).rev() {
+            self.stop_child(i).await;
+        }
+        for i in to_restart {
+            if let Err(e) = self.start_child(i) {
+                warn!("unable to restart child {}: {:?}", self.children[i].spec.id, e);
+                self.stop_children_from(0).await;
+                return Control::Terminate;
+            }
+            #[cfg(feature = "metrics")]
+            {
+                let spec = &self.children[i].spec;
+                self.recorder.restarted(&crate::metrics::label(spec.config.name.as_deref(), spec.type_name));
+            }
+        }
+        Control::Ok
+    }
+}
+
+impl Actor for Supervisor {
+    type SendMessage = SupervisorSends;
+    type CallMessage = SupervisorCalls;
+    type CallReply = SupervisorReplies;
+    type ErrorType = ();
+    type FutureResult = ();
+
+    async fn on_initialization(&mut self, self_ref: ActorRef<Self>) -> Control {
+        self.self_ref = Some(self_ref.downgrade());
+        for idx in 0..self.children.len() {
+            if let Err(e) = self.start_child(idx) {
+                warn!("unable to start child {}: {:?}", self.children[idx].spec.id, e);
+                self.stop_children_from(0).await;
+                return Control::Terminate;
+            }
+        }
+        Control::Ok
+    }
+
+    async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
+        let SupervisorSends(exit) = msg;
+        self.handle_exit(exit).await
+    }
+
+    async fn handle_calls(&mut self, msg: Self::CallMessage) -> (Control, std::result::Result<Self::CallReply, Self::ErrorType>) {
+        match msg {
+            SupervisorCalls::WhichChildren => {
+                let ids = self.children.iter()
+                    .filter(|c| c.running.is_some())
+                    .map(|c| c.spec.id.clone())
+                    .collect();
+                (Control::Ok, Ok(SupervisorReplies::Children(ids)))
+            },
+            SupervisorCalls::GetChild(id) => {
+                let child = self.children.iter()
+                    .find(|c| c.spec.id == id)
+                    .and_then(|c| c.running.as_ref())
+                    .map(|r| r.actor_ref.as_any());
+                (Control::Ok, Ok(SupervisorReplies::Child(child)))
+            },
+        }
+    }
+
+    async fn on_shutdown(&mut self) -> Control {
+        self.stop_children_from(0).await;
+        Control::Ok
+    }
+}
+
+impl ActorRef<Supervisor> {
+    /// Get a reference to the currently running instance of a child of the supervisor.
+    ///
+    /// Returns None if there is no running child with the given id or if the child is not an
+    /// instance of actor A.
+    pub async fn child<A>(&self, id: &str) -> Result<Option<ActorRef<A>>>
+    where
+        A: Actor + Send + Sync + 'static,
+    {
+        match self.call(SupervisorCalls::GetChild(id.to_string())).await? {
+            Ok(SupervisorReplies::Child(child)) => {
+                Ok(child.and_then(|c| c.downcast::<ActorRef<A>>().ok()).map(|c| (*c).clone()))
+            },
+            _ => Err(Error::UnrecognizedMessage),
+        }
+    }
+
+    /// Get the ids of the children of the supervisor that are currently running.
+    pub async fn which_children(&self) -> Result<Vec<String>> {
+        match self.call(SupervisorCalls::WhichChildren).await? {
+            Ok(SupervisorReplies::Children(ids)) => Ok(ids),
+            _ => Err(Error::UnrecognizedMessage),
+        }
+    }
+}
+
+/// Send messages for the [Supervisor]. These are generated internally when children stop.
+#[derive(Clone)]
+pub struct SupervisorSends(ChildExit);
+
+/// Call messages for the [Supervisor].
+///
+/// [ActorRef::child()] and [ActorRef::which_children()] provide a more convenient interface.
+#[derive(Clone)]
+pub enum SupervisorCalls {
+    /// Request the ids of the running children.
+    WhichChildren,
+    /// Request the [ActorRef] of a running child.
+    GetChild(String),
+}
+
+/// Replies to [SupervisorCalls].
+pub enum SupervisorReplies {
+    /// The reply to [SupervisorCalls::WhichChildren].
+    Children(Vec<String>),
+    /// The reply to [SupervisorCalls::GetChild], the value is an [ActorRef].
+    Child(Option<Arc<dyn Any + Send + Sync>>),
+}
+
+/// Notification that a child has stopped.
+#[derive(Clone)]
+struct ChildExit {
+    /// The id of the child.
+    id: String,
+    /// The generation of the child that stopped.
+    generation: u64,
+    /// Why it stopped.
+    reason: ExitReason,
+}
+
+/// A child of the supervisor.
+struct Child {
+    /// The specification of the child.
+    spec: ChildSpec,
+    /// Incremented every time the child is started.
+    generation: u64,
+    /// The running instance, if any.
+    running: Option<RunningChild>,
+}
+
+/// A running instance of a child.
+struct RunningChild {
+    /// The reference to the child.
+    actor_ref: Box<dyn ChildRef>,
+    /// Cancelled when the child has stopped.
+    stopped: CancellationToken,
+}
+
+/// A child that has just been started.
+struct StartedChild {
+    /// The reference to the child.
+    actor_ref: Box<dyn ChildRef>,
+    /// Completes with the exit reason when the child has stopped.
+    handle: Pin<Box<dyn Future<Output = ExitReason> + Send>>,
+}
+
+/// Type-erased access to the [ActorRef] of a child.
+trait ChildRef: Send + Sync {
+    /// Shut down the child.
+    fn shutdown(&self) -> Pin<Box<dyn Future<Output = Result<()>> + Send + '_>>;
+    /// Terminate the child.
+    fn terminate(&self);
+    /// Get a clone of the [ActorRef].
+    fn as_any(&self) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<A> ChildRef for ActorRef<A>
+where
+    A: Actor + Send + Sync + 'static,
+{
+    fn shutdown(&self) -> Pin<Box<dyn Future<Output = Result<()>> + Send + '_>> {
+        Box::pin(ActorRef::shutdown(self))
+    }
+
+    fn terminate(&self) {
+        ActorRef::terminate(self)
+    }
+
+    fn as_any(&self) -> Arc<dyn Any + Send + Sync> {
+        Arc::new(self.clone())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+    use crate::create_actor;
+    use crate::test_code::tests::{eventually, FailingActor, FailingCalls, FailingReplies, FailingSends};
+    use super::*;
+
+    /// Wait until the counter reaches the value, panicking if it takes too long.
+    async fn wait_for(counter: &AtomicU64, value: u64) {
+        eventually(|| async { (counter.load(Ordering::Relaxed) >= value).then_some(()) }).await
+    }
+
+    /// Create a child spec for a FailingActor that counts its starts.
+    fn failing_child(id: &str, starts: &Arc<AtomicU64>) -> ChildSpec {
+        let starts = starts.clone();
+        ChildSpec::new(id, move || FailingActor::with_starts(starts.clone()))
+    }
+
+    /// Make a child of the supervisor fail.
+    async fn fail_child(sup: &ActorRef<Supervisor>, id: &str) {
+        let child = sup.child::<FailingActor>(id).await.unwrap().unwrap();
+        child.send(FailingSends::Fail).await.unwrap();
+    }
+
+    /// Test that one_for_one only restarts the failed child.
+    #[tokio::test]
+    async fn test_one_for_one() {
+        let (a, b) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let sup = Supervisor::new(SupervisorStrategy::OneForOne)
+            .child(failing_child("a", &a))
+            .child(failing_child("b", &b));
+        let (sup_ref, handle) = create_actor(sup).await.unwrap();
+        wait_for(&b, 1).await;
+        fail_child(&sup_ref, "a").await;
+        wait_for(&a, 2).await;
+        assert_eq!(b.load(Ordering::Relaxed), 1);
+        // the restarted child can be reached
+        let child = sup_ref.child::<FailingActor>("a").await.unwrap().unwrap();
+        assert_eq!(child.call(FailingCalls::Ping).await.unwrap(), Ok(FailingReplies::Pong));
+        sup_ref.shutdown().await.unwrap();
+        assert_eq!(handle.await, ExitReason::Shutdown);
+    }
+
+    /// Test that one_for_all restarts all children.
+    #[tokio::test]
+    async fn test_one_for_all() {
+        let (a, b) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let sup = Supervisor::new(SupervisorStrategy::OneForAll)
+            .child(failing_child("a", &a))
+            .child(failing_child("b", &b));
+        let (sup_ref, _handle) = create_actor(sup).await.unwrap();
+        wait_for(&b, 1).await;
+        fail_child(&sup_ref, "b").await;
+        wait_for(&a, 2).await;
+        wait_for(&b, 2).await;
+        assert_eq!(sup_ref.which_children().await.unwrap(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    /// Test that rest_for_one restarts the failed child and the children started after it.
+    #[tokio::test]
+    async fn test_rest_for_one() {
+        let (a, b, c) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let sup = Supervisor::new(SupervisorStrategy::RestForOne)
+            .child(failing_child("a", &a))
+            .child(failing_child("b", &b))
+            .child(failing_child("c", &c));
+        let (sup_ref, _handle) = create_actor(sup).await.unwrap();
+        wait_for(&c, 1).await;
+        fail_child(&sup_ref, "b").await;
+        wait_for(&b, 2).await;
+        wait_for(&c, 2).await;
+        assert_eq!(a.load(Ordering::Relaxed), 1);
+    }
+
+    /// Test that a transient child that stops normally is not restarted.
+    #[tokio::test]
+    async fn test_transient_normal_exit() {
+        let a = Arc::new(AtomicU64::new(0));
+        let sup = Supervisor::new(SupervisorStrategy::OneForOne)
+            .child(failing_child("a", &a).restart(Restart::Transient));
+        let (sup_ref, _handle) = create_actor(sup).await.unwrap();
+        let child = sup_ref.child::<FailingActor>("a").await.unwrap().unwrap();
+        child.send(FailingSends::Stop).await.unwrap();
+        child.watchers.stopped.cancelled().await;
+        // the child is removed once the supervisor has decided not to restart it
+        eventually(|| async { sup_ref.which_children().await.unwrap().is_empty().then_some(()) }).await;
+        assert_eq!(a.load(Ordering::Relaxed), 1);
+    }
+
+    /// Test that exceeding the restart intensity terminates the supervisor and escalates to its parent.
+    #[tokio::test]
+    async fn test_restart_intensity_escalates() {
+        let (a, inner) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let factory_a = a.clone();
+        let factory_inner = inner.clone();
+        let sub = ChildSpec::new("sub", move || {
+            factory_inner.fetch_add(1, Ordering::Relaxed);
+            Supervisor::new(SupervisorStrategy::OneForOne)
+                .max_restarts(1, Duration::from_secs(5))
+                .child(failing_child("a", &factory_a))
+        });
+        let sup = Supervisor::new(SupervisorStrategy::OneForOne).child(sub);
+        let (sup_ref, _handle) = create_actor(sup).await.unwrap();
+        wait_for(&a, 1).await;
+        let sub_ref = sup_ref.child::<Supervisor>("sub").await.unwrap().unwrap();
+        // the first failure is within the restart intensity
+        fail_child(&sub_ref, "a").await;
+        wait_for(&a, 2).await;
+        assert_eq!(inner.load(Ordering::Relaxed), 1);
+        // the second failure exceeds it, so the sub-supervisor is restarted by its parent
+        fail_child(&sub_ref, "a").await;
+        wait_for(&inner, 2).await;
+        wait_for(&a, 3).await;
+    }
+
+    /// Test that a supervisor whose children can not be started fails to initialize.
+    #[tokio::test]
+    async fn test_start_failure() {
+        let starts = Arc::new(AtomicU64::new(0));
+        let spec = failing_child("a", &starts).config(ActorConfig::new().mailbox_size(0));
+        let (_sup_ref, handle) = create_actor(Supervisor::new(SupervisorStrategy::OneForOne).child(spec)).await.unwrap();
+        assert_eq!(handle.await, ExitReason::InitFailed);
+        assert_eq!(starts.load(Ordering::Relaxed), 0);
+    }
+}
@@ -2,14 +2,31 @@
 
 #[cfg(test)]
 pub mod tests {
-    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::future::Future;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
     use std::time::Duration;
-    use crate::{Actor, ActorRef};
+    use crate::{ask_and_pipe, Actor, ActorRef, Down, Handler, Priority, ReplyToken, StreamId};
     use crate::control::Control;
 
     /// an atomic counter that we use for testing
     pub static COUNTER: AtomicU64 = AtomicU64::new(0);
 
+    /// Poll the function until it returns a value, panicking if that takes too long.
+    pub async fn eventually<T, F, Fut>(mut f: F) -> T
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Option<T>>,
+    {
+        for _i in 0..1000 {
+            if let Some(value) = f().await {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        panic!("condition not reached in time");
+    }
+
     /// Message type for DelayingActor sends
     #[derive(Debug, PartialEq, Clone)]
     pub enum DelayingSends {
@@ -20,6 +37,11 @@ pub mod tests {
     #[derive(Debug, PartialEq, Clone)]
     pub enum DelayingCalls {
         DoPong,
+    }
+
+    /// Reply type for DelayingActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum DelayingReplies {
         Pong,
     }
 
@@ -39,7 +61,9 @@ pub mod tests {
     impl Actor for DelayingActor {
         type SendMessage = DelayingSends;
         type CallMessage = DelayingCalls;
+        type CallReply = DelayingReplies;
         type ErrorType = ();
+        type FutureResult = ();
 
         async fn handle_sends(&mut self, _msg: Self::SendMessage) -> Control {
             if !self.waited {
@@ -50,8 +74,8 @@ pub mod tests {
             Control::Ok
         }
 
-        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallMessage, Self::ErrorType>) {
-            (Control::Ok, Ok(DelayingCalls::Pong))
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(DelayingReplies::Pong))
         }
     }
 
@@ -65,7 +89,6 @@ pub mod tests {
     #[derive(Debug, PartialEq, Clone)]
     pub enum CounterCalls {
         GetCount,
-        Reply(u64),
     }
 
     /// Simple actor for testing purposes. It counts.
@@ -85,7 +108,9 @@ pub mod tests {
     impl Actor for SimpleCounter {
         type SendMessage = CounterSends;
         type CallMessage = CounterCalls;
+        type CallReply = u64;
         type ErrorType = ();
+        type FutureResult = ();
 
         async fn on_initialization(&mut self, _self_ref: ActorRef<Self>) -> Control {
             if self.immediate_quit {
@@ -100,8 +125,535 @@ pub mod tests {
             Control::Ok
         }
 
-        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallMessage, Self::ErrorType>) {
-            (Control::Ok, Ok(CounterCalls::Reply(self.count)))
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(self.count))
+        }
+    }
+
+    /// Message type for FailingActor sends
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum FailingSends {
+        Fail,
+        Stop,
+    }
+
+    /// Message type for FailingActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum FailingCalls {
+        Ping,
+    }
+
+    /// Reply type for FailingActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum FailingReplies {
+        Pong,
+    }
+
+    /// Simple actor for testing purposes. It terminates when sent a Fail message and counts how
+    /// many times it has been started. It can also be made to fail its initialization, or to
+    /// panic during it.
+    pub struct FailingActor {
+        starts: Arc<AtomicU64>,
+        fail_init: bool,
+        panic_init: bool,
+    }
+
+    impl FailingActor {
+        pub fn new() -> Self {
+            Self::with_starts(Arc::new(AtomicU64::new(0)))
+        }
+
+        pub fn with_starts(starts: Arc<AtomicU64>) -> Self {
+            Self {
+                starts,
+                fail_init: false,
+                panic_init: false,
+            }
+        }
+
+        pub fn failing_init() -> Self {
+            Self {
+                fail_init: true,
+                ..Self::new()
+            }
+        }
+
+        pub fn panicking_init() -> Self {
+            Self {
+                panic_init: true,
+                ..Self::new()
+            }
+        }
+    }
+
+    impl Actor for FailingActor {
+        type SendMessage = FailingSends;
+        type CallMessage = FailingCalls;
+        type CallReply = FailingReplies;
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn on_initialization(&mut self, _self_ref: ActorRef<Self>) -> Control {
+            self.starts.fetch_add(1, Ordering::Relaxed);
+            if self.fail_init {
+                return Control::Terminate;
+            }
+            if self.panic_init {
+                panic!("init panic");
+            }
+            Control::Ok
+        }
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
+            match msg {
+                FailingSends::Fail => Control::Terminate,
+                FailingSends::Stop => Control::Shutdown,
+            }
+        }
+
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(FailingReplies::Pong))
+        }
+    }
+
+    /// Message type for PanickingActor sends
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum PanickingSends {
+        Panic,
+    }
+
+    /// Message type for PanickingActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum PanickingCalls {
+        Panic,
+        Ping,
+    }
+
+    /// Reply type for PanickingActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum PanickingReplies {
+        Pong,
+    }
+
+    /// Simple actor for testing purposes. It panics when asked to and records whether on_panic()
+    /// was called.
+    pub struct PanickingActor {
+        cleaned_up: Arc<AtomicBool>,
+    }
+
+    impl PanickingActor {
+        pub fn new(cleaned_up: Arc<AtomicBool>) -> Self {
+            Self {
+                cleaned_up,
+            }
+        }
+    }
+
+    impl Actor for PanickingActor {
+        type SendMessage = PanickingSends;
+        type CallMessage = PanickingCalls;
+        type CallReply = PanickingReplies;
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_sends(&mut self, _msg: Self::SendMessage) -> Control {
+            panic!("send panic");
+        }
+
+        async fn handle_calls(&mut self, msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
+            match msg {
+                PanickingCalls::Panic => panic!("call panic"),
+                _ => (Control::Ok, Ok(PanickingReplies::Pong)),
+            }
+        }
+
+        async fn on_panic(&mut self, _message: &str) {
+            self.cleaned_up.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Simple actor for testing purposes. It records the down notifications it receives.
+    pub struct DownRecorder {
+        downs: Arc<Mutex<Vec<Down>>>,
+    }
+
+    impl DownRecorder {
+        pub fn new(downs: Arc<Mutex<Vec<Down>>>) -> Self {
+            Self {
+                downs,
+            }
+        }
+    }
+
+    impl Actor for DownRecorder {
+        type SendMessage = ();
+        type CallMessage = ();
+        type CallReply = ();
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_down(&mut self, down: Down) -> Control {
+            self.downs.lock().unwrap().push(down);
+            Control::Ok
+        }
+    }
+
+    /// Message type for FutureActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum FutureCalls {
+        Get,
+    }
+
+    /// Simple actor for testing purposes. It doubles the values sent to it in a spawned future
+    /// and stores the result.
+    pub struct FutureActor {
+        value: Option<u64>,
+    }
+
+    impl FutureActor {
+        pub fn new() -> Self {
+            Self {
+                value: None,
+            }
+        }
+    }
+
+    impl Actor for FutureActor {
+        type SendMessage = u64;
+        type CallMessage = FutureCalls;
+        type CallReply = Option<u64>;
+        type ErrorType = ();
+        type FutureResult = u64;
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control<Self::FutureResult> {
+            Control::SpawnFutureWithResult(Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                msg * 2
+            }))
+        }
+
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control<Self::FutureResult>, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(self.value))
+        }
+
+        async fn handle_future(&mut self, result: Self::FutureResult) -> Control<Self::FutureResult> {
+            self.value = Some(result);
+            Control::Ok
+        }
+    }
+
+    /// Message type for StreamActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum StreamCalls {
+        Get,
+    }
+
+    /// Simple actor for testing purposes. It sums the values sent to it and records which
+    /// streams have finished.
+    pub struct StreamActor {
+        sum: u64,
+        finished: Vec<StreamId>,
+    }
+
+    impl StreamActor {
+        pub fn new() -> Self {
+            Self {
+                sum: 0,
+                finished: Vec::new(),
+            }
+        }
+    }
+
+    impl Actor for StreamActor {
+        type SendMessage = u64;
+        type CallMessage = StreamCalls;
+        type CallReply = (u64, Vec<StreamId>);
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
+            self.sum += msg;
+            Control::Ok
+        }
+
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok((self.sum, self.finished.clone())))
+        }
+
+        async fn on_stream_finished(&mut self, id: StreamId) -> Control {
+            self.finished.push(id);
+            Control::Ok
+        }
+    }
+
+    /// Message type for PriorityActor sends
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum PrioritySends {
+        Normal(u32),
+        Urgent(u32),
+    }
+
+    /// Simple actor for testing purposes. It records the order in which it handles messages and
+    /// delays on the first message received.
+    pub struct PriorityActor {
+        handled: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl PriorityActor {
+        pub fn new(handled: Arc<Mutex<Vec<u32>>>) -> Self {
+            Self {
+                handled,
+            }
+        }
+    }
+
+    impl Actor for PriorityActor {
+        type SendMessage = PrioritySends;
+        type CallMessage = ();
+        type CallReply = ();
+        type ErrorType = ();
+        type FutureResult = ();
+
+        fn send_priority(msg: &Self::SendMessage) -> Priority {
+            match msg {
+                PrioritySends::Normal(_) => Priority::Normal,
+                PrioritySends::Urgent(_) => Priority::High,
+            }
+        }
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
+            if self.handled.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            let (PrioritySends::Normal(n) | PrioritySends::Urgent(n)) = msg;
+            self.handled.lock().unwrap().push(n);
+            Control::Ok
+        }
+
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(()))
+        }
+    }
+
+    /// Simple actor for testing purposes. It records whether on_shutdown() was called.
+    pub struct ShutdownRecorder {
+        shut_down: Arc<AtomicBool>,
+    }
+
+    impl ShutdownRecorder {
+        pub fn new(shut_down: Arc<AtomicBool>) -> Self {
+            Self {
+                shut_down,
+            }
+        }
+    }
+
+    impl Actor for ShutdownRecorder {
+        type SendMessage = ();
+        type CallMessage = ();
+        type CallReply = ();
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_sends(&mut self, _msg: Self::SendMessage) -> Control {
+            Control::Ok
+        }
+
+        async fn on_shutdown(&mut self) -> Control {
+            self.shut_down.store(true, Ordering::Relaxed);
+            Control::Ok
+        }
+    }
+
+    /// Handler message for HandlerActor, adds the value to the total
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Add(pub u64);
+
+    /// Handler message for HandlerActor, gets the total
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct GetTotal;
+
+    /// Handler message for HandlerActor, describes the total
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Describe;
+
+    /// Handler message for HandlerActor, panics
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct Explode;
+
+    /// Simple actor for testing purposes. It keeps a total using several [Handler] implementations
+    /// and also adds the values sent to it.
+    pub struct HandlerActor {
+        total: u64,
+    }
+
+    impl HandlerActor {
+        pub fn new() -> Self {
+            Self {
+                total: 0,
+            }
+        }
+    }
+
+    impl Actor for HandlerActor {
+        type SendMessage = u64;
+        type CallMessage = ();
+        type CallReply = ();
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
+            self.total += msg;
+            Control::Ok
+        }
+    }
+
+    impl Handler<Add> for HandlerActor {
+        type Response = ();
+
+        async fn handle(&mut self, msg: Add) -> (Control, ()) {
+            self.total += msg.0;
+            (Control::Ok, ())
+        }
+    }
+
+    impl Handler<GetTotal> for HandlerActor {
+        type Response = u64;
+
+        async fn handle(&mut self, _msg: GetTotal) -> (Control, u64) {
+            (Control::Ok, self.total)
+        }
+    }
+
+    impl Handler<Describe> for HandlerActor {
+        type Response = String;
+
+        async fn handle(&mut self, _msg: Describe) -> (Control, String) {
+            (Control::Ok, format!("total is {}", self.total))
+        }
+    }
+
+    impl Handler<Explode> for HandlerActor {
+        type Response = ();
+
+        async fn handle(&mut self, _msg: Explode) -> (Control, ()) {
+            panic!("handler panic");
+        }
+    }
+
+    /// Message type for DeferringActor calls
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum DeferringCalls {
+        /// Wait for the next send message and reply with its value.
+        Wait,
+        /// Get the number of waiting calls.
+        Waiting,
+        /// Reply with the value from a spawned future.
+        Later(u64),
+        /// Drop the reply token.
+        Forget,
+    }
+
+    /// Simple actor for testing purposes. It answers calls later, using reply tokens.
+    pub struct DeferringActor {
+        waiting: Vec<ReplyToken<Self>>,
+    }
+
+    impl DeferringActor {
+        pub fn new() -> Self {
+            Self {
+                waiting: Vec::new(),
+            }
+        }
+    }
+
+    impl Actor for DeferringActor {
+        type SendMessage = u64;
+        type CallMessage = DeferringCalls;
+        type CallReply = u64;
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control {
+            for reply in self.waiting.drain(..) {
+                reply.reply(Ok(msg));
+            }
+            Control::Ok
+        }
+
+        async fn handle_calls_deferred(&mut self, msg: Self::CallMessage, reply: ReplyToken<Self>) -> Control {
+            match msg {
+                DeferringCalls::Wait => self.waiting.push(reply),
+                DeferringCalls::Waiting => reply.reply(Ok(self.waiting.len() as u64)),
+                DeferringCalls::Later(value) => {
+                    return Control::SpawnFuture(Box::pin(async move {
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        reply.reply(Ok(value));
+                    }));
+                },
+                DeferringCalls::Forget => {},
+            }
+            Control::Ok
+        }
+    }
+
+    /// Simple actor for testing purposes. When sent a message, it asks the counter for its count
+    /// using ask_and_pipe() and records the result.
+    pub struct AskingActor {
+        counter: ActorRef<SimpleCounter>,
+        results: Vec<Option<u64>>,
+    }
+
+    impl AskingActor {
+        pub fn new(counter: ActorRef<SimpleCounter>) -> Self {
+            Self {
+                counter,
+                results: Vec::new(),
+            }
+        }
+    }
+
+    impl Actor for AskingActor {
+        type SendMessage = ();
+        type CallMessage = ();
+        type CallReply = Vec<Option<u64>>;
+        type ErrorType = ();
+        type FutureResult = Option<u64>;
+
+        async fn handle_sends(&mut self, _msg: Self::SendMessage) -> Control<Self::FutureResult> {
+            ask_and_pipe(&self.counter, CounterCalls::GetCount, |r| r.ok().and_then(|r| r.ok()))
+        }
+
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control<Self::FutureResult>, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(self.results.clone()))
+        }
+
+        async fn handle_future(&mut self, result: Self::FutureResult) -> Control<Self::FutureResult> {
+            self.results.push(result);
+            Control::Ok
+        }
+    }
+
+    /// Simple actor for testing purposes. It spawns a future that holds on to the value sent to
+    /// it and never completes.
+    pub struct PendingActor;
+
+    impl Actor for PendingActor {
+        type SendMessage = Arc<()>;
+        type CallMessage = ();
+        type CallReply = ();
+        type ErrorType = ();
+        type FutureResult = ();
+
+        async fn handle_sends(&mut self, msg: Self::SendMessage) -> Control<Self::FutureResult> {
+            Control::SpawnFuture(Box::pin(async move {
+                let _held = msg;
+                futures::future::pending::<()>().await
+            }))
+        }
+
+        async fn handle_calls(&mut self, _msg: Self::CallMessage) -> (Control<Self::FutureResult>, Result<Self::CallReply, Self::ErrorType>) {
+            (Control::Ok, Ok(()))
         }
     }
 }
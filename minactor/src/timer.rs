@@ -0,0 +1,178 @@
+use std::time::Duration;
+use tokio::select;
+use tokio::time::{Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use crate::{Actor, ActorRef, Error};
+
+
+/// A handle to a timer created by [ActorRef::send_after()] or [ActorRef::send_interval()].
+///
+/// The timer can be cancelled using the handle. Dropping the handle does not cancel the timer.
+/// All timers of an actor are cancelled automatically when the actor stops. Timers do not keep
+/// the actor alive.
+#[derive(Debug, Clone)]
+pub struct TimerHandle {
+    /// Cancelled when the timer is cancelled, has fired for the last time, or the actor stopped.
+    token: CancellationToken,
+}
+
+impl TimerHandle {
+    /// Cancel the timer. Messages that have not yet been sent will not be sent.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Returns true if the timer may still send messages.
+    pub fn is_active(&self) -> bool {
+        !self.token.is_cancelled()
+    }
+}
+
+impl<A> ActorRef<A>
+where
+    A: Actor + Send + Sync + 'static
+{
+    /// Send a message to the actor after a delay.
+    ///
+    /// If the mailbox of the actor is full when the delay has passed, then the message is dropped.
+    /// The timer does not wait for space, so that it does not keep the actor alive.
+    pub fn send_after(&self, delay: Duration, msg: A::SendMessage) -> TimerHandle {
+        // the delay is measured from now, not from when the timer task starts
+        let deadline = Instant::now() + delay;
+        let token = self.watchers.stopped.child_token();
+        let timer_token = token.clone();
+        let weak = self.downgrade();
+        tokio::spawn(async move {
+            select! {
+                biased;
+                _ = timer_token.cancelled() => {},
+                _ = tokio::time::sleep_until(deadline) => {
+                    // an error means the actor has stopped or its mailbox is full
+                    if let Some(actor_ref) = weak.upgrade() {
+                        let _ = actor_ref.try_send(msg);
+                    }
+                    timer_token.cancel();
+                }
+            }
+        });
+        TimerHandle { token }
+    }
+
+    /// Send a message to the actor repeatedly, with the given period.
+    ///
+    /// The first message is sent after one period. If the mailbox of the actor is full when a
+    /// message is due, then that message is dropped. The timer does not wait for space, so that
+    /// it does not keep the actor alive. Ticks that are missed because the timer could not run
+    /// on time are handled according to the [MissedTickBehavior].
+    pub fn send_interval(&self, period: Duration, msg: A::SendMessage, missed_ticks: MissedTickBehavior) -> TimerHandle {
+        let start = Instant::now() + period;
+        let token = self.watchers.stopped.child_token();
+        let timer_token = token.clone();
+        let weak = self.downgrade();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(start, period);
+            interval.set_missed_tick_behavior(missed_ticks);
+            loop {
+                select! {
+                    biased;
+                    _ = timer_token.cancelled() => { break; },
+                    _ = interval.tick() => {
+                        let Some(actor_ref) = weak.upgrade() else {
+                            timer_token.cancel();
+                            break;
+                        };
+                        // a full mailbox only drops this message
+                        if let Err(e) = actor_ref.try_send(msg.clone()) {
+                            if matches!(e.error(), Error::ActorStopped { .. }) {
+                                timer_token.cancel();
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        TimerHandle { token }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::{create_actor, create_actor_with_config, ActorConfig, ExitReason};
+    use crate::test_code::tests::{CounterCalls, CounterSends, PriorityActor, PrioritySends, SimpleCounter};
+    use super::*;
+
+    /// Get the count of the counter actor.
+    async fn count(actor: &ActorRef<SimpleCounter>) -> u64 {
+        actor.call(CounterCalls::GetCount).await.unwrap().unwrap()
+    }
+
+    /// Advance the paused clock and let the timers that fired send their messages.
+    async fn advance(duration: Duration) {
+        tokio::time::advance(duration).await;
+        tokio::task::yield_now().await;
+    }
+
+    /// Test that send_after sends the message after the delay and that it can be cancelled.
+    #[tokio::test(start_paused = true)]
+    async fn test_send_after() {
+        let (actor, _handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let timer = actor.send_after(Duration::from_millis(10), CounterSends::Count);
+        let cancelled = actor.send_after(Duration::from_millis(10), CounterSends::Count);
+        cancelled.cancel();
+        advance(Duration::from_millis(9)).await;
+        assert_eq!(count(&actor).await, 0);
+        assert!(timer.is_active());
+        advance(Duration::from_millis(1)).await;
+        assert_eq!(count(&actor).await, 1);
+        assert!(!timer.is_active());
+    }
+
+    /// Test that send_interval sends messages until it is cancelled.
+    #[tokio::test(start_paused = true)]
+    async fn test_send_interval() {
+        let (actor, _handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let timer = actor.send_interval(Duration::from_millis(5), CounterSends::Count, MissedTickBehavior::Skip);
+        for _i in 0..10 {
+            advance(Duration::from_millis(5)).await;
+        }
+        assert_eq!(count(&actor).await, 10);
+        timer.cancel();
+        for _i in 0..4 {
+            advance(Duration::from_millis(5)).await;
+        }
+        assert_eq!(count(&actor).await, 10);
+    }
+
+    /// Test that timers are cancelled when the actor stops.
+    #[tokio::test]
+    async fn test_timers_stop_with_actor() {
+        let (actor, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let timer = actor.send_interval(Duration::from_millis(5), CounterSends::Count, MissedTickBehavior::Skip);
+        assert!(timer.is_active());
+        actor.shutdown().await.unwrap();
+        handle.await;
+        assert!(!timer.is_active());
+    }
+
+    /// Test that a timer does not wait for space in a full mailbox, so that it does not keep the
+    /// actor alive.
+    #[tokio::test(start_paused = true)]
+    async fn test_full_mailbox() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let config = ActorConfig::new().mailbox_size(1);
+        let (actor, handle) = create_actor_with_config(PriorityActor::new(handled.clone()), config).await.unwrap();
+        // the actor sleeps while handling the first message, the second fills the mailbox
+        actor.send(PrioritySends::Normal(0)).await.unwrap();
+        tokio::task::yield_now().await;
+        actor.send(PrioritySends::Normal(1)).await.unwrap();
+        let timer = actor.send_after(Duration::from_millis(1), PrioritySends::Normal(2));
+        advance(Duration::from_millis(1)).await;
+        assert!(!timer.is_active());
+        drop(actor);
+        assert_eq!(handle.await, ExitReason::Normal);
+        assert_eq!(*handled.lock().unwrap(), vec![0, 1]);
+    }
+}
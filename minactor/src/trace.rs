@@ -0,0 +1,131 @@
+use tracing::Span;
+use crate::{ActorInfo, MessageKind};
+
+
+/// Create the span in which the actor handles a message.
+///
+/// The parent is the span of the sender when the message was sent, so that traces continue
+/// across actors. Messages that do not have a sender, such as items from attached streams, are
+/// handled in a new root span.
+pub(crate) fn handler_span(actor: &ActorInfo, parent: &Span, kind: MessageKind) -> Span {
+    tracing::info_span!(
+        parent: parent,
+        "handle",
+        actor.name = actor.name(),
+        actor.id = %actor.id(),
+        message.kind = kind.as_str(),
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+    use tracing::{Instrument, Metadata};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::subscriber::Subscriber;
+    use tracing_core::span::Current;
+    use crate::{create_actor_with_config, ActorConfig};
+    use crate::test_code::tests::{CounterCalls, CounterSends, SimpleCounter};
+
+    /// A span recorded by the [Recorder].
+    struct RecordedSpan {
+        name: &'static str,
+        metadata: &'static Metadata<'static>,
+        parent: Option<Id>,
+        fields: HashMap<String, String>,
+    }
+
+    /// A subscriber that records the spans that are created.
+    #[derive(Default)]
+    struct Recorder {
+        /// The spans, the id of a span is its index plus one.
+        spans: Mutex<Vec<RecordedSpan>>,
+        /// The spans that have been entered.
+        stack: Mutex<Vec<Id>>,
+    }
+
+    impl Recorder {
+        /// The spans with the name, as pairs of the parent and the fields.
+        fn spans(&self, name: &str) -> Vec<(Option<&'static str>, HashMap<String, String>)> {
+            let spans = self.spans.lock().unwrap();
+            spans.iter()
+                .filter(|s| s.name == name)
+                .map(|s| (s.parent.as_ref().map(|p| spans[p.into_u64() as usize - 1].name), s.fields.clone()))
+                .collect()
+        }
+    }
+
+    impl Visit for RecordedSpan {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.fields.insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.fields.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let parent = if attrs.is_contextual() {
+                self.stack.lock().unwrap().last().cloned()
+            } else {
+                attrs.parent().cloned()
+            };
+            let mut span = RecordedSpan { name: attrs.metadata().name(), metadata: attrs.metadata(), parent, fields: HashMap::new() };
+            attrs.record(&mut span);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(span);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &tracing::Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.stack.lock().unwrap().push(span.clone());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.stack.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> Current {
+            match self.stack.lock().unwrap().last() {
+                Some(id) => Current::new(id.clone(), self.spans.lock().unwrap()[id.into_u64() as usize - 1].metadata),
+                None => Current::none(),
+            }
+        }
+    }
+
+    /// Test that messages are handled in spans that are children of the span of the sender.
+    #[tokio::test]
+    async fn test_handler_spans() {
+        let recorder = Arc::new(Recorder::default());
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let config = ActorConfig::new().name("traced");
+        let (actor, _handle) = create_actor_with_config(SimpleCounter::new(false), config).await.unwrap();
+        actor.send(CounterSends::Count).instrument(tracing::info_span!("sender")).await.unwrap();
+        let count = actor.call(CounterCalls::GetCount).instrument(tracing::info_span!("caller")).await.unwrap();
+        assert_eq!(count, Ok(1));
+        let spans = recorder.spans("handle");
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].0, Some("sender"));
+        assert_eq!(spans[0].1["message.kind"], "send");
+        assert_eq!(spans[1].0, Some("caller"));
+        assert_eq!(spans[1].1["message.kind"], "call");
+        assert_eq!(spans[1].1["actor.name"], "traced");
+        assert_eq!(spans[1].1["actor.id"], actor.id().to_string());
+    }
+}
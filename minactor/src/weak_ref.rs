@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use crate::{Actor, ActorId, ActorRef};
+use crate::mailbox::WeakMailboxSender;
+use crate::result::ActorInfo;
+use crate::monitor::Watchers;
+
+
+/// A weak reference to an actor, created using [ActorRef::downgrade()].
+///
+/// A WeakActorRef does not keep the mailbox of the actor open, which makes it suitable for caches,
+/// registries and observer lists that should not affect the lifetime of the actor. Use
+/// [upgrade()](WeakActorRef::upgrade) to get an [ActorRef] to send messages to the actor.
+pub struct WeakActorRef<A>
+where A: Actor + ?Sized
+{
+    /// The weak channel to the actor.
+    outbox: WeakMailboxSender<A>,
+    /// [CancellationToken] to terminate the actor.
+    pub(crate) terminate_token: CancellationToken,
+    /// The id of the actor.
+    id: ActorId,
+    /// The monitors and links of the actor.
+    pub(crate) watchers: Arc<Watchers>,
+    /// The name of the actor.
+    name: Option<Arc<str>>,
+}
+
+impl<A> WeakActorRef<A> where A: Actor {
+    /// Get the id of the actor.
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// Information about the actor, for use in errors.
+    pub(crate) fn info(&self) -> ActorInfo {
+        ActorInfo::new(self.id, self.name.clone())
+    }
+
+    /// Get an [ActorRef] to the actor.
+    ///
+    /// Returns None if the actor has stopped or there are no other ActorRefs to the actor.
+    pub fn upgrade(&self) -> Option<ActorRef<A>> {
+        if self.watchers.stopped.is_cancelled() {
+            return None;
+        }
+        Some(ActorRef {
+            outbox: self.outbox.upgrade()?,
+            terminate_token: self.terminate_token.clone(),
+            id: self.id,
+            watchers: self.watchers.clone(),
+            name: self.name.clone(),
+        })
+    }
+}
+
+impl<A> Clone for WeakActorRef<A>
+where
+    A: Actor,
+{
+    fn clone(&self) -> Self {
+        Self {
+            outbox: self.outbox.clone(),
+            terminate_token: self.terminate_token.clone(),
+            id: self.id,
+            watchers: self.watchers.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+impl<A> ActorRef<A>
+where A: Actor
+{
+    /// Create a [WeakActorRef] to the actor, which does not keep the actor alive.
+    pub fn downgrade(&self) -> WeakActorRef<A> {
+        WeakActorRef {
+            outbox: self.outbox.downgrade(),
+            terminate_token: self.terminate_token.clone(),
+            id: self.id,
+            watchers: self.watchers.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::create_actor;
+    use crate::test_code::tests::{CounterCalls, CounterSends, SimpleCounter};
+
+    /// Test that a weak reference can be upgraded while the actor is running.
+    #[tokio::test]
+    async fn test_upgrade() {
+        let (actor, handle) = create_actor(SimpleCounter::new(false)).await.unwrap();
+        let weak = actor.downgrade();
+        assert_eq!(weak.id(), actor.id());
+        let strong = weak.upgrade().unwrap();
+        strong.send(CounterSends::Count).await.unwrap();
+        assert_eq!(actor.call(CounterCalls::GetCount).await.unwrap(), Ok(1));
+        actor.shutdown().await.unwrap();
+        handle.await;
+        // the actor has stopped
+        assert!(weak.upgrade().is_none());
+    }
+}